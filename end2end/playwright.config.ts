@@ -98,10 +98,15 @@ const config: PlaywrightTestConfig = {
   // outputDir: 'test-results/',
 
   /* Run your local dev server before starting the tests */
-  // webServer: {
-  //   command: 'npm run start',
-  //   port: 3000,
-  // },
+  /* Serve the fixture records on a fake Airtable. Run the site against it with
+   * AIRTABLE_ENDPOINT=http://127.0.0.1:3001/v0/ AIRTABLE_BASE_ID=appFakeUnregistry */
+  webServer: {
+    command:
+      "cargo run --features ssr --bin fake-airtable -- --port 3001 --base appFakeUnregistry fixtures/airtable.json",
+    port: 3001,
+    timeout: 300 * 1000,
+    reuseExistingServer: true,
+  },
 };
 
 export default config;
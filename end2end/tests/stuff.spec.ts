@@ -0,0 +1,16 @@
+import { test, expect } from "@playwright/test";
+
+// Runs against the records in fixtures/airtable.json, served by fake-airtable.
+test("stuff page lists the published items", async ({ page }) => {
+  await page.goto("http://localhost:8080/stuff");
+
+  await expect(page.getByText("tennis racket")).toBeVisible();
+  await expect(page.getByText("yoga mat")).toBeVisible();
+  await expect(page.getByText("snowboard")).toHaveCount(0);
+});
+
+test("items without thumbnails still show a preview", async ({ page }) => {
+  await page.goto("http://localhost:8080/stuff");
+
+  await expect(page.locator('img[src="/home-page.png"]')).toHaveCount(2);
+});
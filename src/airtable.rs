@@ -6,19 +6,23 @@
  * Example:
  *
  * ```ignore
- * use airtable_api::{Airtable, Record};
+ * use airtable_api::{Airtable, Formula, ListOptions, Record};
  * use serde::{Deserialize, Serialize};
  *
  * async fn get_records() {
  *     // Initialize the Airtable client.
- *     let airtable = Airtable::new_from_env();
+ *     let airtable = Airtable::new_from_env().unwrap();
  *
  *     // Get the current records from a table.
  *     let mut records: Vec<Record<SomeFormat>> = airtable
  *         .list_records(
  *             "Table Name",
  *             "Grid view",
- *             vec!["the", "fields", "you", "want", "to", "return"],
+ *             &ListOptions {
+ *                 fields: vec!["x".to_string()],
+ *                 filter_by_formula: Some(Formula::field("x").equals(true)),
+ *                 ..Default::default()
+ *             },
  *         )
  *         .await
  *         .unwrap();
@@ -37,27 +41,85 @@
  */
 #![allow(clippy::field_reassign_with_default, dead_code)]
 // use log::info;
-use std::{env, fmt, fmt::Debug, marker::PhantomData};
+use std::{
+    collections::VecDeque,
+    env, fmt,
+    fmt::Debug,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
 
-use anyhow::{bail, Result};
 use chrono::{offset::Utc, DateTime};
+use futures::{FutureExt, Stream, TryStreamExt};
 use reqwest::{header, Method, Request, StatusCode, Url};
 use schemars::JsonSchema;
 use serde::{
-    de::{DeserializeOwned, MapAccess, SeqAccess, Visitor},
+    de::{DeserializeOwned, IgnoredAny, MapAccess, SeqAccess, Visitor},
     Deserialize, Deserializer, Serialize,
 };
 
-/// Endpoint for the Airtable API.
-const ENDPOINT: &str = "https://api.airtable.com/v0/";
+mod bases;
+mod builder;
+mod bulk;
+#[cfg(feature = "ssr")]
+mod cassette;
+pub mod codegen;
+mod comments;
+mod error;
+#[cfg(feature = "ssr")]
+pub mod fake;
+mod fields;
+mod formula;
+mod links;
+mod meta;
+#[cfg(feature = "ssr")]
+mod rate_limit;
+mod table;
+#[cfg(feature = "ssr")]
+mod upload;
+mod validate;
+pub mod webhooks;
+
+pub use bases::AirtableBases;
+pub use builder::AirtableBuilder;
+pub use bulk::{BulkReport, BulkWriter, ChunkOutcome, ChunkReport};
+#[cfg(feature = "ssr")]
+pub use cassette::{Cassette, CassetteError, CassetteMode};
+pub use comments::{Comment, Mention};
+pub use error::{AirtableError, ApiError};
+pub use fields::{
+    AiText, Button, Checkbox, Computed, CreatedBy, Currency, Duration, LastModifiedBy,
+    LastModifiedTime, Lookup, MultipleSelects, Percent, Rating, RecordLinks, RichText, Rollup,
+    SingleSelect,
+};
+pub use formula::{Comparison, Formula, ParseFormulaError};
+pub use links::Link;
+pub use meta::{
+    Base, Choice, FieldOptions, FieldResult, FieldSchema, FieldType, FieldUpdate, NewField,
+    NewTable, TableSchema, ViewSchema,
+};
+#[cfg(feature = "ssr")]
+pub use rate_limit::RateLimiter;
+pub use table::Table;
+#[cfg(feature = "ssr")]
+pub use upload::MAX_UPLOAD_SIZE;
+pub use validate::{JsonKind, SchemaIssue, SchemaReport, SchemaValidator};
+
+type Result<T, E = AirtableError> = std::result::Result<T, E>;
 
 /// Entrypoint for interacting with the Airtable API.
 pub struct Airtable {
     key: String,
     base_id: String,
     enterprise_account_id: String,
+    endpoint: Url,
+    #[cfg(feature = "ssr")]
+    content_endpoint: Url,
 
     pub(crate) client: reqwest_middleware::ClientWithMiddleware,
+    /// Sends the requests that are never retried: streamed uploads, which
+    /// can't be resent, and bulk creates, which would create the records twice.
+    once_client: reqwest_middleware::ClientWithMiddleware,
 }
 
 /// Get the API key from the AIRTABLE_API_KEY env variable.
@@ -71,47 +133,31 @@ impl Airtable {
     /// given a valid API Key and Base ID your requests will work.
     /// You can leave the Enterprise Account ID empty if you are not using the
     /// Enterprise API features.
-    pub fn new<K, B, E>(key: K, base_id: B, enterprise_account_id: E) -> Self
+    pub fn new<K, B, E>(key: K, base_id: B, enterprise_account_id: E) -> Result<Self>
     where
         K: ToString,
         B: ToString,
         E: ToString,
     {
-        let http = reqwest::Client::builder().build();
-        match http {
-            Ok(c) => {
-                let retry_policy = reqwest_retry::policies::ExponentialBackoff::builder()
-                    .build_with_max_retries(3);
-                let client = reqwest_middleware::ClientBuilder::new(c)
-                    // Trace HTTP requests. See the tracing crate to make use of these traces.
-                    .with(reqwest_tracing::TracingMiddleware::default())
-                    // Retry failed requests.
-                    .with(reqwest_retry::RetryTransientMiddleware::new_with_policy(
-                        retry_policy,
-                    ))
-                    .build();
-
-                Self {
-                    key: key.to_string(),
-                    base_id: base_id.to_string(),
-                    enterprise_account_id: enterprise_account_id.to_string(),
-
-                    client,
-                }
-            }
-            Err(err) => panic!("creating client failed: {err:?}"),
-        }
+        AirtableBuilder::new(key, base_id)
+            .enterprise_account_id(enterprise_account_id)
+            .build()
     }
 
-    /// Create a new Airtable client struct from environment variables. It
-    /// takes a type that can convert into
-    /// an &str (`String` or `Vec<u8>` for example). As long as the function is
-    /// given a valid API Key and Base ID your requests will work.
-    pub fn new_from_env() -> Self {
-        let base_id = env::var("AIRTABLE_BASE_ID").unwrap_or_default();
-        let enterprise_account_id = env::var("AIRTABLE_ENTERPRISE_ACCOUNT_ID").unwrap_or_default();
+    /// Create a new Airtable client struct from environment variables, see
+    /// [`AirtableBuilder::from_env`].
+    pub fn new_from_env() -> Result<Self> {
+        AirtableBuilder::from_env().build()
+    }
 
-        Airtable::new(api_key_from_env(), base_id, enterprise_account_id)
+    /// Start building a client with a custom endpoint, timeout, retry policy,
+    /// user agent, middleware or proxy.
+    pub fn builder<K, B>(key: K, base_id: B) -> AirtableBuilder
+    where
+        K: ToString,
+        B: ToString,
+    {
+        AirtableBuilder::new(key, base_id)
     }
 
     /// Get the currently set API key.
@@ -119,6 +165,7 @@ impl Airtable {
         &self.key
     }
 
+    /// Build a request for a path within the base, e.g. a table.
     pub(crate) fn request<B>(
         &self,
         method: Method,
@@ -129,11 +176,42 @@ impl Airtable {
     where
         B: Serialize,
     {
-        let base = Url::parse(ENDPOINT)?;
-        let url = base.join(&(self.base_id.to_string() + "/" + &path))?;
+        self.request_to(method, self.base_id.to_string() + "/" + &path, body, query)
+    }
+
+    /// Build a request for a path within the metadata API, e.g. `bases`.
+    pub(crate) fn meta_request<B>(
+        &self,
+        method: Method,
+        path: String,
+        body: B,
+        query: Option<Vec<(&str, String)>>,
+    ) -> Result<Request>
+    where
+        B: Serialize,
+    {
+        self.request_to(method, "meta/".to_string() + &path, body, query)
+    }
+
+    /// Build a request for a path relative to the API endpoint.
+    fn request_to<B>(
+        &self,
+        method: Method,
+        path: String,
+        body: B,
+        query: Option<Vec<(&str, String)>>,
+    ) -> Result<Request>
+    where
+        B: Serialize,
+    {
+        let url = self
+            .endpoint
+            .join(&path)
+            .map_err(|e| AirtableError::Config(e.to_string()))?;
 
         let bt = format!("Bearer {}", self.key);
-        let bearer = header::HeaderValue::from_str(&bt)?;
+        let bearer = header::HeaderValue::from_str(&bt)
+            .map_err(|_| AirtableError::Config("invalid API key".to_string()))?;
 
         // Set the default headers.
         let mut headers = header::HeaderMap::new();
@@ -161,78 +239,25 @@ impl Airtable {
         Ok(rb.build()?)
     }
 
-    /// List records in a table for a particular view.
-    pub async fn list_records<T: DeserializeOwned>(
+    /// List records in a table for a particular view. An empty `view` lists
+    /// every record in the table.
+    pub async fn list_records<T: DeserializeOwned + Send + Unpin>(
         &self,
         table: &str,
         view: &str,
-        // fields: Vec<&str>,
+        options: &ListOptions,
     ) -> Result<Vec<Record<T>>> {
-        // let mut params = vec![("pageSize", "100".to_string()), ("view", view.to_string())];
-        let params = vec![("pageSize", "100".to_string()), ("view", view.to_string())];
-        // for field in fields {
-        //     params.push(("fields[]", field.to_string()));
-        // }
-
-        // Build the request.
-        let mut request = self.request(Method::GET, table.to_string(), (), Some(params))?;
-
-        let mut resp = self.client.execute(request).await?;
-
-        match resp.status() {
-            StatusCode::OK => (),
-            s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
-            }
-        };
-
-        // Try to deserialize the response.
-        let mut r: APICall<T> = resp.json().await?;
-
-        let mut records = r.records;
-
-        let mut offset = r.offset;
-
-        // Paginate if we should.
-        // TODO: make this more DRY
-        while !offset.is_empty() {
-            request = self.request(
-                Method::GET,
-                table.to_string(),
-                (),
-                Some(vec![
-                    ("pageSize", "100".to_string()),
-                    ("view", view.to_string()),
-                    ("offset", offset),
-                ]),
-            )?;
-
-            resp = self.client.execute(request).await?;
-            match resp.status() {
-                StatusCode::OK => (),
-                s => {
-                    bail!("status code: {}, body: {}", s, resp.text().await?);
-                }
-            };
-
-            // Try to deserialize the response.
-            r = resp.json().await?;
-
-            records.append(&mut r.records);
-
-            offset = r.offset;
-        }
-
-        Ok(records)
+        self.pages(table, view, options.clone()).try_collect().await
     }
 
-    pub fn pages<T: DeserializeOwned>(
-        &self,
+    /// Page through the records in a table for a particular view.
+    pub fn pages<'a, T: DeserializeOwned + Send + 'a>(
+        &'a self,
         table: &str,
         view: &str,
-        fields: Vec<&str>,
-    ) -> Pages<T> {
-        Pages::new(self, table, view, &fields)
+        options: ListOptions,
+    ) -> Pages<'a, T> {
+        Pages::new(self, table, view, options)
     }
 
     /// Get record from a table.
@@ -247,13 +272,11 @@ impl Airtable {
         let resp = self.client.execute(request).await?;
         match resp.status() {
             StatusCode::OK => (),
-            s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
-            }
+            _ => return Err(AirtableError::from_response(resp).await),
         };
 
         // Try to deserialize the response.
-        let record: Record<T> = resp.json().await?;
+        let record: Record<T> = error::json(resp).await?;
 
         Ok(record)
     }
@@ -271,9 +294,7 @@ impl Airtable {
         let resp = self.client.execute(request).await?;
         match resp.status() {
             StatusCode::OK => (),
-            s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
-            }
+            _ => return Err(AirtableError::from_response(resp).await),
         };
 
         Ok(())
@@ -282,12 +303,30 @@ impl Airtable {
     /// Delete multiple records from a table.
     ///
     /// Due to limitations on the Airtable API, you can only bulk delete 10
-    /// records at a time.
-    pub async fn delete_records(
+    /// records at a time. Because of this, calls to this function containing
+    /// more than 10 records will send multiple requests (one request for each
+    /// chunk of 10 records) to the Airtable API.
+    pub async fn delete_records<'a>(
         &self,
         table: &str,
-        record_ids: impl IntoIterator<Item = &str>,
-    ) -> Result<()> {
+        record_ids: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<Deleted>> {
+        let record_ids: Vec<&str> = record_ids.into_iter().collect();
+
+        let mut res = Vec::new();
+        for chunk in record_ids.chunks(10) {
+            res.extend(self.delete_records_inner(table, chunk).await?);
+        }
+
+        Ok(res)
+    }
+
+    /// Delete multiple records from a table.
+    ///
+    /// The provided `record_ids` slice MUST contain at most 10 items, or this function will panic.
+    async fn delete_records_inner(&self, table: &str, record_ids: &[&str]) -> Result<Vec<Deleted>> {
+        assert!(record_ids.len() <= 10);
+
         // Build the request.
         let request = self.request(
             Method::DELETE,
@@ -295,7 +334,7 @@ impl Airtable {
             (),
             Some(
                 record_ids
-                    .into_iter()
+                    .iter()
                     .map(|record_id| ("records[]", record_id.to_string()))
                     .collect(),
             ),
@@ -304,12 +343,44 @@ impl Airtable {
         let resp = self.client.execute(request).await?;
         match resp.status() {
             StatusCode::OK => (),
-            s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: DeleteResponse = error::json(resp).await?;
+
+        Ok(r.records)
+    }
+
+    /// Delete every record in a table matching `filter`.
+    ///
+    /// The matching record ids are listed first and then deleted in batches of
+    /// 10, so records created while this runs are left alone. Only the primary
+    /// field is listed when the API key can read the schema, every field
+    /// otherwise.
+    pub async fn delete_where(&self, table: &str, filter: Formula) -> Result<Vec<Deleted>> {
+        let fields = match self.get_table_schema(table).await {
+            Ok(schema) => vec![schema.primary_field_id],
+            Err(e) => {
+                log::debug!("[airtable-api] Listing every field of table {table}: {e}");
+                Vec::new()
             }
         };
 
-        Ok(())
+        let records: Vec<Record<IgnoredAny>> = self
+            .list_records(
+                table,
+                "",
+                &ListOptions {
+                    fields,
+                    filter_by_formula: Some(filter),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        self.delete_records(table, records.iter().map(|r| r.id.as_str()))
+            .await
     }
 
     /// Bulk create records in a table.
@@ -317,6 +388,9 @@ impl Airtable {
     /// The Airtable API limits record creation requests to 10 records per request. Because of
     /// this, calls to this function containing more than 10 records will send multiple requests
     /// (one request for each chunk of 10 records) to the Airtable API.
+    ///
+    /// Unlike the other writes a failed request is not retried, since a request
+    /// that timed out may still have created its records.
     pub async fn create_records<T: Serialize + DeserializeOwned>(
         &self,
         table: &str,
@@ -337,7 +411,8 @@ impl Airtable {
         }
     }
 
-    /// Bulk create records in a table.
+    /// Bulk create records in a table. Failed requests are not retried, a
+    /// request that timed out may still have created its records.
     ///
     /// The provided `records` vector MUST contain at most 10 items, or this function will panic.
     async fn create_records_inner<T: Serialize + DeserializeOwned>(
@@ -359,22 +434,23 @@ impl Airtable {
             None,
         )?;
 
-        let resp = self.client.execute(request).await?;
+        let resp = self.once_client.execute(request).await?;
         match resp.status() {
             StatusCode::OK => (),
-            s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
-            }
+            _ => return Err(AirtableError::from_response(resp).await),
         };
 
         // Try to deserialize the response.
-        let r: APICall<T> = resp.json().await?;
+        let r: APICall<T> = error::json(resp).await?;
 
         Ok(r.records)
     }
 
     /// Bulk update records in a table.
     ///
+    /// Only the fields that are serialized are changed, use [`Patch`] to clear
+    /// a field explicitly.
+    ///
     /// The Airtable API limits record update requests to 10 records per request. Because of
     /// this, calls to this function containing more than 10 records will send multiple requests
     /// (one request for each chunk of 10 records) to the Airtable API.
@@ -382,16 +458,46 @@ impl Airtable {
         &self,
         table: &str,
         records: Vec<Record<T>>,
+    ) -> Result<Vec<Record<T>>> {
+        self.update_records_with(Method::PATCH, table, records)
+            .await
+    }
+
+    /// Bulk replace records in a table.
+    ///
+    /// Unlike [`Airtable::update_records`] this is destructive: every field that is
+    /// not serialized is cleared.
+    ///
+    /// The Airtable API limits record update requests to 10 records per request. Because of
+    /// this, calls to this function containing more than 10 records will send multiple requests
+    /// (one request for each chunk of 10 records) to the Airtable API.
+    pub async fn replace_records<T: Serialize + DeserializeOwned>(
+        &self,
+        table: &str,
+        records: Vec<Record<T>>,
+    ) -> Result<Vec<Record<T>>> {
+        self.update_records_with(Method::PUT, table, records).await
+    }
+
+    /// Bulk update records in a table with either PATCH or PUT semantics.
+    async fn update_records_with<T: Serialize + DeserializeOwned>(
+        &self,
+        method: Method,
+        table: &str,
+        records: Vec<Record<T>>,
     ) -> Result<Vec<Record<T>>> {
         if records.len() <= 10 {
-            self.update_records_inner(table, records).await
+            self.update_records_inner(method, table, records).await
         } else {
             let mut res = Vec::new();
 
             let mut records = records.into_iter();
             while records.len() > 0 {
                 let chunk = (&mut records).take(10).collect();
-                res.extend(self.update_records_inner(table, chunk).await?);
+                res.extend(
+                    self.update_records_inner(method.clone(), table, chunk)
+                        .await?,
+                );
             }
 
             Ok(res)
@@ -403,6 +509,7 @@ impl Airtable {
     /// The provided `records` vector MUST contain at most 10 items, or this function will panic.
     async fn update_records_inner<T: Serialize + DeserializeOwned>(
         &self,
+        method: Method,
         table: &str,
         records: Vec<Record<T>>,
     ) -> Result<Vec<Record<T>>> {
@@ -410,7 +517,7 @@ impl Airtable {
 
         // Build the request.
         let request = self.request(
-            Method::PATCH,
+            method,
             table.to_string(),
             APICall {
                 records,
@@ -423,37 +530,99 @@ impl Airtable {
         let resp = self.client.execute(request).await?;
         match resp.status() {
             StatusCode::OK => (),
-            s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
-            }
+            _ => return Err(AirtableError::from_response(resp).await),
         };
 
         // Try to deserialize the response.
-        match resp.json::<APICall<T>>().await {
-            Ok(v) => Ok(v.records),
-            Err(_) => {
-                // This might fail. On a faiture just return an empty vector.
-                Ok(vec![])
+        let r: APICall<T> = error::json(resp).await?;
+
+        Ok(r.records)
+    }
+
+    /// Bulk create or update records in a table, matching existing records on
+    /// the values of the `merge_on` fields.
+    ///
+    /// Records whose `merge_on` fields match exactly one existing record update
+    /// it, the others are created. Like [`Airtable::create_records`], calls
+    /// containing more than 10 records will send one request per chunk of 10.
+    /// FROM: https://airtable.com/developers/web/api/update-multiple-records
+    pub async fn upsert_records<T: Serialize + DeserializeOwned>(
+        &self,
+        table: &str,
+        records: Vec<Record<T>>,
+        merge_on: &[&str],
+    ) -> Result<Upserted<T>> {
+        if records.len() <= 10 {
+            self.upsert_records_inner(table, records, merge_on).await
+        } else {
+            let mut res = Upserted::default();
+
+            let mut records = records.into_iter();
+            while records.len() > 0 {
+                let chunk = (&mut records).take(10).collect();
+                res.extend(self.upsert_records_inner(table, chunk, merge_on).await?);
             }
+
+            Ok(res)
         }
     }
 
+    /// Bulk create or update records in a table.
+    ///
+    /// The provided `records` vector MUST contain at most 10 items, or this function will panic.
+    async fn upsert_records_inner<T: Serialize + DeserializeOwned>(
+        &self,
+        table: &str,
+        records: Vec<Record<T>>,
+        merge_on: &[&str],
+    ) -> Result<Upserted<T>> {
+        assert!(records.len() <= 10);
+
+        // Build the request.
+        let request = self.request(
+            Method::PATCH,
+            table.to_string(),
+            UpsertCall {
+                perform_upsert: PerformUpsert {
+                    fields_to_merge_on: merge_on.iter().map(|f| f.to_string()).collect(),
+                },
+                records,
+                typecast: Some(true),
+            },
+            None,
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: Upserted<T> = error::json(resp).await?;
+
+        Ok(r)
+    }
+
+    /// Write records to `table` in concurrent chunks, reporting the outcome
+    /// of each chunk instead of stopping at the first error.
+    pub fn bulk_writer(&self, table: &str) -> BulkWriter<'_> {
+        BulkWriter::new(self, table)
+    }
+
     /// List users.
     /// This is for an enterprise admin to do only.
     /// FROM: https://airtable.com/api/enterprise
     pub async fn list_users(&self) -> Result<Vec<User>> {
         if self.enterprise_account_id.is_empty() {
             // Return an error early.
-            bail!("An enterprise account id is required.");
+            return Err(AirtableError::MissingEnterpriseAccount);
         }
 
         // Build the request.
-        let request = self.request(
+        let request = self.meta_request(
             Method::GET,
-            format!(
-                "v0/meta/enterpriseAccounts/{}/users",
-                self.enterprise_account_id
-            ),
+            format!("enterpriseAccounts/{}/users", self.enterprise_account_id),
             (),
             Some(vec![("state", "provisioned".to_string())]),
         )?;
@@ -461,13 +630,11 @@ impl Airtable {
         let resp = self.client.execute(request).await?;
         match resp.status() {
             StatusCode::OK => (),
-            s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
-            }
+            _ => return Err(AirtableError::from_response(resp).await),
         };
 
         // Try to deserialize the response.
-        let result: UsersResponse = resp.json().await?;
+        let result: UsersResponse = error::json(resp).await?;
 
         Ok(result.users)
     }
@@ -479,16 +646,13 @@ impl Airtable {
     pub async fn get_enterprise_user(&self, email: &str) -> Result<EnterpriseUser> {
         if self.enterprise_account_id.is_empty() {
             // Return an error early.
-            bail!("An enterprise account id is required.");
+            return Err(AirtableError::MissingEnterpriseAccount);
         }
 
         // Build the request.
-        let request = self.request(
+        let request = self.meta_request(
             Method::GET,
-            format!(
-                "v0/meta/enterpriseAccounts/{}/users",
-                self.enterprise_account_id
-            ),
+            format!("enterpriseAccounts/{}/users", self.enterprise_account_id),
             (),
             Some(vec![
                 ("email", email.to_string()),
@@ -500,15 +664,16 @@ impl Airtable {
 
         match resp.status() {
             StatusCode::OK => (),
-            s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
-            }
+            _ => return Err(AirtableError::from_response(resp).await),
         };
 
-        let r: EnterpriseUsersResponse = resp.json().await?;
+        let r: EnterpriseUsersResponse = error::json(resp).await?;
 
         if r.users.is_empty() {
-            bail!("no user was returned");
+            return Err(AirtableError::NotFound(ApiError {
+                type_: "NOT_FOUND".to_string(),
+                message: "no user was returned".to_string(),
+            }));
         }
 
         Ok(r.users.get(0).unwrap().clone())
@@ -526,13 +691,13 @@ impl Airtable {
     ) -> Result<()> {
         if self.enterprise_account_id.is_empty() {
             // Return an error early.
-            bail!("An enterprise account id is required.");
+            return Err(AirtableError::MissingEnterpriseAccount);
         }
 
         // Build the request.
-        let request = self.request(
+        let request = self.meta_request(
             Method::POST,
-            format!("v0/meta/workspaces/{workspace_id}/collaborators"),
+            format!("workspaces/{workspace_id}/collaborators"),
             NewCollaborator {
                 collaborators: vec![Collaborator {
                     user: User {
@@ -549,9 +714,7 @@ impl Airtable {
         let resp = self.client.execute(request).await?;
         match resp.status() {
             StatusCode::OK => (),
-            s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
-            }
+            _ => return Err(AirtableError::from_response(resp).await),
         };
 
         Ok(())
@@ -567,13 +730,13 @@ impl Airtable {
     ) -> Result<Workspace> {
         if self.enterprise_account_id.is_empty() {
             // Return an error early.
-            bail!("An enterprise account id is required.");
+            return Err(AirtableError::MissingEnterpriseAccount);
         }
 
         // Build the request.
-        let request = self.request(
+        let request = self.meta_request(
             Method::GET,
-            format!("v0/meta/workspaces/{workspace_id}?"),
+            format!("workspaces/{workspace_id}"),
             (),
             includes.map(|includes| {
                 includes
@@ -593,12 +756,10 @@ impl Airtable {
         let resp = self.client.execute(request).await?;
         match resp.status() {
             StatusCode::OK => (),
-            s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
-            }
+            _ => return Err(AirtableError::from_response(resp).await),
         };
 
-        let r: Workspace = resp.json().await?;
+        let r: Workspace = error::json(resp).await?;
 
         Ok(r)
     }
@@ -610,16 +771,13 @@ impl Airtable {
     pub async fn delete_internal_user_by_email(&self, email: &str) -> Result<()> {
         if self.enterprise_account_id.is_empty() {
             // Return an error early.
-            bail!("An enterprise account id is required.");
+            return Err(AirtableError::MissingEnterpriseAccount);
         }
 
         // Build the request.
-        let request = self.request(
+        let request = self.meta_request(
             Method::DELETE,
-            format!(
-                "v0/meta/enterpriseAccounts/{}/users",
-                self.enterprise_account_id
-            ),
+            format!("enterpriseAccounts/{}/users", self.enterprise_account_id),
             (),
             Some(vec![("email", email.to_string())]),
         )?;
@@ -627,103 +785,323 @@ impl Airtable {
         let resp = self.client.execute(request).await?;
         match resp.status() {
             StatusCode::OK => (),
-            s => {
-                bail!("status code: {}, body: {}", s, resp.text().await?);
-            }
+            _ => return Err(AirtableError::from_response(resp).await),
         };
 
         // Try to deserialize the response.
-        let result: DeleteUserResponse = resp.json().await?;
+        let result: DeleteUserResponse = error::json(resp).await?;
         if !result.errors.is_empty() {
-            bail!("body: {:?}", result);
+            return Err(AirtableError::DeleteUsers {
+                errors: result.errors,
+            });
         }
 
         Ok(())
     }
 }
 
+/// Pages through the records of a table.
+///
+/// `Pages` is a [`Stream`] of records that fetches the next page lazily, once
+/// the records of the previous page have been consumed, so combinators like
+/// `take` and `try_filter` only request the pages they need. Whole pages can be
+/// fetched with [`Pages::next_page`] instead.
 pub struct Pages<'a, T> {
     client: &'a Airtable,
     table: String,
     view: String,
-    fields: Vec<String>,
+    options: ListOptions,
     offset: Option<String>,
-    record_type: PhantomData<T>,
+    buffer: VecDeque<Record<T>>,
+    in_flight: Option<PageFuture<'a, T>>,
 }
 
+/// The request for a page. Reqwest futures are not `Send` on wasm, where the
+/// client runs in the browser.
+#[cfg(not(target_arch = "wasm32"))]
+type PageFuture<'a, T> = futures::future::BoxFuture<'a, Result<APICall<T>>>;
+#[cfg(target_arch = "wasm32")]
+type PageFuture<'a, T> = futures::future::LocalBoxFuture<'a, Result<APICall<T>>>;
+
 impl<'a, T> Pages<'a, T>
 where
-    T: DeserializeOwned,
+    T: DeserializeOwned + Send + 'a,
 {
-    pub fn new(client: &'a Airtable, table: &str, view: &str, fields: &[&str]) -> Self {
+    pub fn new(client: &'a Airtable, table: &str, view: &str, options: ListOptions) -> Self {
         Self {
             client,
             table: table.to_string(),
             view: view.to_string(),
-            fields: fields.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+            options,
             offset: Some(String::new()),
-            record_type: PhantomData,
+            buffer: VecDeque::new(),
+            in_flight: None,
         }
     }
 
-    pub async fn next(&mut self) -> Result<Option<Vec<Record<T>>>> {
-        if self.offset.is_none() {
+    /// Start the request for the next page, or return `None` if there are no
+    /// more pages. Pagination stops once a request fails.
+    fn fetch(&mut self) -> Option<PageFuture<'a, T>> {
+        let Some(offset) = self.offset.take() else {
             log::debug!("[airtable-api] Page does not have an offset. Returning.");
-            return Ok(None);
+            return None;
+        };
+
+        let mut params = self.options.params(&self.view);
+
+        if offset.is_empty() {
+            log::debug!("[airtable-api] Requesting first page of records");
+        } else {
+            log::debug!(
+                "[airtable-api] Fetching page of results with offset {}",
+                offset
+            );
+            params.push(("offset".to_string(), offset));
         }
 
-        let mut params = vec![
-            ("pageSize", "100".to_string()),
-            ("view", self.view.to_string()),
-        ];
+        // Build the request.
+        let request = self.client.request(
+            Method::GET,
+            self.table.to_string(),
+            (),
+            Some(
+                params
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.clone()))
+                    .collect(),
+            ),
+        );
 
-        if let Some(offset) = &self.offset {
-            if !offset.is_empty() {
-                log::debug!(
-                    "[airtable-api] Fetching page of results with offset {}",
-                    offset
-                );
-                params.push(("offset", offset.clone()));
+        let client = self.client;
+        let lenient = self.options.lenient;
+        Some(Box::pin(async move {
+            let response = client.client.execute(request?).await?;
+
+            match response.status() {
+                StatusCode::OK => {
+                    let api_response: APICall<T> = if lenient {
+                        error::json_lenient(response).await?
+                    } else {
+                        error::json(response).await?
+                    };
+                    log::debug!("[airtable-api] Retrieved page response");
+
+                    Ok(api_response)
+                }
+                _ => {
+                    log::debug!(
+                        "[airtable-api] Pagination request returned an error. Stopping requests."
+                    );
+
+                    Err(AirtableError::from_response(response).await)
+                }
             }
+        }))
+    }
+
+    /// Remember where the next page starts.
+    fn set_offset(&mut self, offset: String) {
+        self.offset = if !offset.is_empty() {
+            Some(offset)
         } else {
-            log::debug!("[airtable-api] Requesting first page of records");
+            None
+        };
+    }
+
+    /// Fetch the next whole page of records.
+    ///
+    /// This should not be mixed with polling `Pages` as a [`Stream`], which
+    /// buffers the records of the current page.
+    pub async fn next_page(&mut self) -> Result<Option<Vec<Record<T>>>> {
+        let Some(fetch) = self.fetch() else {
+            return Ok(None);
+        };
+
+        let api_response = fetch.await?;
+        self.set_offset(api_response.offset);
+
+        Ok(Some(api_response.records))
+    }
+
+    /// Fetch the next whole page of records, the name [`Pages::next_page`]
+    /// had before `Pages` became a [`Stream`]. It shadows `StreamExt::next`,
+    /// use `TryStreamExt::try_next` to get single records.
+    #[deprecated(note = "renamed to `next_page`")]
+    pub async fn next(&mut self) -> Result<Option<Vec<Record<T>>>> {
+        self.next_page().await
+    }
+}
+
+impl<'a, T> Stream for Pages<'a, T>
+where
+    T: DeserializeOwned + Send + Unpin + 'a,
+{
+    type Item = Result<Record<T>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        loop {
+            if let Some(record) = this.buffer.pop_front() {
+                return Poll::Ready(Some(Ok(record)));
+            }
+
+            let in_flight = match &mut this.in_flight {
+                Some(in_flight) => in_flight,
+                None => match this.fetch() {
+                    Some(fetch) => this.in_flight.insert(fetch),
+                    None => return Poll::Ready(None),
+                },
+            };
+
+            let result = ready!(in_flight.poll_unpin(cx));
+            this.in_flight = None;
+
+            match result {
+                Ok(api_response) => {
+                    this.set_offset(api_response.offset);
+                    this.buffer.extend(api_response.records);
+                }
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+/// Options for listing records.
+/// FROM: https://airtable.com/developers/web/api/list-records
+#[derive(Debug, Default, Clone)]
+pub struct ListOptions {
+    /// Only return these fields. All fields are returned when empty.
+    pub fields: Vec<String>,
+    /// Only return records for which this formula is true.
+    pub filter_by_formula: Option<Formula>,
+    /// Sort records by these fields, in order. Overrides the sorting of the view.
+    pub sort: Vec<Sort>,
+    /// The maximum total number of records returned across all pages.
+    pub max_records: Option<u32>,
+    /// The number of records returned in each page, at most 100 (the default).
+    pub page_size: Option<u32>,
+    /// How cell values are returned. `CellFormat::String` requires
+    /// `time_zone` and `user_locale` to be set.
+    pub cell_format: Option<CellFormat>,
+    /// The time zone used to format dates when `cell_format` is `String`.
+    pub time_zone: Option<String>,
+    /// The locale used to format dates when `cell_format` is `String`.
+    pub user_locale: Option<String>,
+    /// Key the returned fields by field id instead of field name.
+    pub return_fields_by_field_id: bool,
+    /// Leave out the fields that don't deserialize into `T` and note them in
+    /// [`Record::warnings`], instead of failing the whole page. Records that
+    /// still don't deserialize, e.g. because a required field is left out,
+    /// are skipped and logged.
+    pub lenient: bool,
+}
+
+impl ListOptions {
+    /// Build the query parameters for a list request against `view`.
+    fn params(&self, view: &str) -> Vec<(String, String)> {
+        let mut params = vec![(
+            "pageSize".to_string(),
+            self.page_size.unwrap_or(100).to_string(),
+        )];
+
+        if !view.is_empty() {
+            params.push(("view".to_string(), view.to_string()));
         }
 
         for field in &self.fields {
-            params.push(("fields[]", field.to_string()));
+            params.push(("fields[]".to_string(), field.to_string()));
         }
 
-        // Build the request.
-        let request = self
-            .client
-            .request(Method::GET, self.table.to_string(), (), Some(params))?;
+        if let Some(filter) = &self.filter_by_formula {
+            params.push(("filterByFormula".to_string(), filter.to_string()));
+        }
 
-        let response = self.client.client.execute(request).await?;
+        for (i, sort) in self.sort.iter().enumerate() {
+            params.push((format!("sort[{i}][field]"), sort.field.to_string()));
+            params.push((format!("sort[{i}][direction]"), sort.direction.to_string()));
+        }
 
-        match response.status() {
-            StatusCode::OK => {
-                let api_response: APICall<T> = response.json().await?;
-                log::debug!("[airtable-api] Retrieved page response");
+        if let Some(max_records) = self.max_records {
+            params.push(("maxRecords".to_string(), max_records.to_string()));
+        }
 
-                self.offset = if !api_response.offset.is_empty() {
-                    Some(api_response.offset)
-                } else {
-                    None
-                };
+        if let Some(cell_format) = self.cell_format {
+            params.push(("cellFormat".to_string(), cell_format.to_string()));
+        }
 
-                Ok(Some(api_response.records))
-            }
-            s => {
-                log::debug!(
-                    "[airtable-api] Pagination request returned an error. Stopping requests."
-                );
+        if let Some(time_zone) = &self.time_zone {
+            params.push(("timeZone".to_string(), time_zone.to_string()));
+        }
 
-                // Once we hit an error we stop pagination
-                self.offset = None;
+        if let Some(user_locale) = &self.user_locale {
+            params.push(("userLocale".to_string(), user_locale.to_string()));
+        }
 
-                bail!("status code: {}, body: {}", s, response.text().await?);
-            }
+        if self.return_fields_by_field_id {
+            params.push(("returnFieldsByFieldId".to_string(), "true".to_string()));
         }
+
+        params
+    }
+}
+
+/// Sort records by a field.
+#[derive(Debug, Clone)]
+pub struct Sort {
+    pub field: String,
+    pub direction: SortDirection,
+}
+
+impl Sort {
+    /// Sort by `field` in ascending order.
+    pub fn asc<S: ToString>(field: S) -> Self {
+        Sort {
+            field: field.to_string(),
+            direction: SortDirection::Asc,
+        }
+    }
+
+    /// Sort by `field` in descending order.
+    pub fn desc<S: ToString>(field: S) -> Self {
+        Sort {
+            field: field.to_string(),
+            direction: SortDirection::Desc,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl fmt::Display for SortDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SortDirection::Asc => "asc",
+            SortDirection::Desc => "desc",
+        })
+    }
+}
+
+/// The format used for cell values in list responses.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CellFormat {
+    #[default]
+    Json,
+    String,
+}
+
+impl fmt::Display for CellFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CellFormat::Json => "json",
+            CellFormat::String => "string",
+        })
     }
 }
 
@@ -744,6 +1122,134 @@ struct APICall<T> {
     pub typecast: Option<bool>,
 }
 
+/// The request body for upserting records.
+#[derive(Debug, Clone, Serialize)]
+struct UpsertCall<T> {
+    #[serde(rename = "performUpsert")]
+    pub perform_upsert: PerformUpsert,
+    pub records: Vec<Record<T>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub typecast: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PerformUpsert {
+    #[serde(rename = "fieldsToMergeOn")]
+    pub fields_to_merge_on: Vec<String>,
+}
+
+/// The result of upserting records.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Upserted<T> {
+    /// Every created or updated record.
+    pub records: Vec<Record<T>>,
+    /// The ids of the records that were created.
+    #[serde(default, rename = "createdRecords")]
+    pub created_records: Vec<String>,
+    /// The ids of the existing records that were updated.
+    #[serde(default, rename = "updatedRecords")]
+    pub updated_records: Vec<String>,
+}
+
+impl<T> Default for Upserted<T> {
+    fn default() -> Self {
+        Self {
+            records: Vec::new(),
+            created_records: Vec::new(),
+            updated_records: Vec::new(),
+        }
+    }
+}
+
+impl<T> Upserted<T> {
+    /// Append the results of another request.
+    fn extend(&mut self, other: Upserted<T>) {
+        self.records.extend(other.records);
+        self.created_records.extend(other.created_records);
+        self.updated_records.extend(other.updated_records);
+    }
+}
+
+/// A field in a partial update.
+///
+/// `Option` cannot tell "leave this field alone" apart from "clear this
+/// field", since `None` is either skipped or sent as `null`. Use `Patch` for
+/// fields that may need clearing, together with
+/// `#[serde(default, skip_serializing_if = "Patch::is_unchanged")]`.
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct ItemUpdate {
+///     #[serde(default, skip_serializing_if = "Patch::is_unchanged")]
+///     price: Patch<i32>,
+/// }
+///
+/// // Remove the price, leaving every other field as is.
+/// let update = ItemUpdate { price: Patch::Clear };
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum Patch<T> {
+    /// The field is not sent and keeps its current value.
+    #[default]
+    Unchanged,
+    /// The field is set to this value.
+    Set(T),
+    /// The field is sent as `null`, clearing it.
+    Clear,
+}
+
+impl<T> Patch<T> {
+    pub fn is_unchanged(&self) -> bool {
+        matches!(self, Patch::Unchanged)
+    }
+
+    /// The new value of the field, if it is being set.
+    pub fn as_set(&self) -> Option<&T> {
+        match self {
+            Patch::Set(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for Patch<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Patch::Set(v) => serializer.serialize_some(v),
+            Patch::Unchanged | Patch::Clear => serializer.serialize_none(),
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // A missing field is `Unchanged` through `#[serde(default)]`.
+        Ok(match Option::<T>::deserialize(deserializer)? {
+            Some(v) => Patch::Set(v),
+            None => Patch::Clear,
+        })
+    }
+}
+
+/// The response returned from deleting records.
+#[derive(Debug, Clone, Deserialize)]
+struct DeleteResponse {
+    records: Vec<Deleted>,
+}
+
+/// A record that was deleted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deleted {
+    pub id: String,
+    pub deleted: bool,
+}
+
 /// An Airtable record.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Record<T> {
@@ -752,9 +1258,29 @@ pub struct Record<T> {
     pub fields: T,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_time: Option<DateTime<Utc>>,
+    /// The fields left out because they didn't deserialize, when listing
+    /// with [`ListOptions::lenient`].
+    #[serde(skip)]
+    pub warnings: Vec<FieldWarning>,
+}
+
+/// A field of a record that could not be deserialized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldWarning {
+    pub field: String,
+    /// Where in the fields the value that failed is, e.g. `image[0].size`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for FieldWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "field `{}`: {}", self.path, self.message)
+    }
 }
 
-/// An airtable user.
+/// An airtable user, also the value of collaborator, created by and last
+/// modified by fields.
 #[derive(Debug, Default, Clone, Serialize, JsonSchema, Deserialize)]
 pub struct User {
     #[serde(default, skip_serializing_if = "String::is_empty")]
@@ -871,7 +1397,7 @@ impl<'de> Visitor<'de> for UsersVisitor {
     type Value = Vec<User>;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("a very special vector")
+        formatter.write_str("a list of users")
     }
 
     fn visit_seq<A: SeqAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
@@ -924,7 +1450,7 @@ pub struct AttachmentShort {
     pub url: String,
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, JsonSchema, Deserialize)]
 pub struct Attachment {
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub id: String,
@@ -934,23 +1460,56 @@ pub struct Attachment {
     pub filename: String,
     #[serde(default)]
     pub size: i64,
+    /// The MIME type, e.g. `image/jpeg` or `application/pdf`.
     #[serde(default, skip_serializing_if = "String::is_empty", rename = "type")]
     pub type_: String,
-    #[serde(default)]
-    pub thumbnails: Thumbnails,
+    /// The size of an image in pixels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<i64>,
+    /// Airtable only makes thumbnails of images and of some documents, and
+    /// not before it has processed the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnails: Option<Thumbnails>,
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+impl Attachment {
+    pub fn is_image(&self) -> bool {
+        self.type_.starts_with("image/")
+    }
+
+    /// The url of the large thumbnail, or of the image itself while it has
+    /// no thumbnails. `None` for other files without thumbnails.
+    pub fn preview_url(&self) -> Option<&str> {
+        let thumbnail = self
+            .thumbnails
+            .as_ref()
+            .and_then(|t| t.large.as_ref().or(t.full.as_ref()))
+            .map(|t| t.url.as_str())
+            .filter(|url| !url.is_empty());
+
+        match thumbnail {
+            Some(url) => Some(url),
+            None if self.is_image() && !self.url.is_empty() => Some(&self.url),
+            None => None,
+        }
+    }
+}
+
+/// The thumbnails of an attachment, each of which can be missing.
+#[derive(Debug, Default, Clone, Serialize, JsonSchema, Deserialize)]
 pub struct Thumbnails {
-    #[serde(default)]
-    pub small: Full,
-    #[serde(default)]
-    pub large: Full,
-    #[serde(default)]
-    pub full: Full,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub small: Option<Full>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub large: Option<Full>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full: Option<Full>,
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+/// A thumbnail of an attachment.
+#[derive(Debug, Default, Clone, Serialize, JsonSchema, Deserialize)]
 pub struct Full {
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub url: String,
@@ -1186,7 +1745,7 @@ impl<'de> Visitor<'de> for AttachmentsVisitor {
     type Value = Vec<Attachment>;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("a very special vector")
+        formatter.write_str("a list of attachments")
     }
 
     fn visit_seq<A: SeqAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
@@ -1219,14 +1778,13 @@ pub mod user_format_as_array_of_strings {
         S: Serializer,
     {
         // Make our array of Airtable user objects.
-        let mut seq = serializer.serialize_seq(Some(array.len())).unwrap();
+        let mut seq = serializer.serialize_seq(Some(array.len()))?;
         for e in array {
             seq.serialize_element(&User {
                 id: Default::default(),
                 email: e.to_string(),
                 name: Default::default(),
-            })
-            .unwrap();
+            })?;
         }
         seq.end()
     }
@@ -1242,7 +1800,7 @@ pub mod user_format_as_array_of_strings {
     where
         D: Deserializer<'de>,
     {
-        let airtable_users = deserializer.deserialize_seq(UsersVisitor {}).unwrap();
+        let airtable_users = deserializer.deserialize_seq(UsersVisitor {})?;
 
         let mut users: Vec<String> = Default::default();
         for a in airtable_users {
@@ -1285,9 +1843,7 @@ pub mod user_format_as_string {
     where
         D: Deserializer<'de>,
     {
-        let user = deserializer
-            .deserialize_struct("User", USERFIELDS, UserVisitor)
-            .unwrap();
+        let user = deserializer.deserialize_struct("User", USERFIELDS, UserVisitor)?;
         Ok(user.email)
     }
 }
@@ -1309,11 +1865,11 @@ pub mod attachment_format_as_array_of_strings {
         S: Serializer,
     {
         // Make our array of Airtable attachment objects.
-        let mut seq = serializer.serialize_seq(Some(array.len())).unwrap();
+        let mut seq = serializer.serialize_seq(Some(array.len()))?;
         for e in array {
             let mut attachment: AttachmentShort = Default::default();
             attachment.url = e.to_string();
-            seq.serialize_element(&attachment).unwrap();
+            seq.serialize_element(&attachment)?;
         }
         seq.end()
     }
@@ -1329,7 +1885,7 @@ pub mod attachment_format_as_array_of_strings {
     where
         D: Deserializer<'de>,
     {
-        let airtable_attachments = deserializer.deserialize_seq(AttachmentsVisitor {}).unwrap();
+        let airtable_attachments = deserializer.deserialize_seq(AttachmentsVisitor {})?;
 
         let mut attachments: Vec<String> = Default::default();
         for a in airtable_attachments {
@@ -1340,6 +1896,8 @@ pub mod attachment_format_as_array_of_strings {
     }
 }
 
+/// Attach a file by its public URL. Files without one can be uploaded with
+/// [`Airtable::upload_attachment`].
 pub mod attachment_format_as_string {
     use serde::{self, ser::SerializeSeq, Deserializer, Serializer};
 
@@ -1357,10 +1915,10 @@ pub mod attachment_format_as_string {
         S: Serializer,
     {
         // Make our array of Airtable attachment objects.
-        let mut seq = serializer.serialize_seq(Some(1)).unwrap();
+        let mut seq = serializer.serialize_seq(Some(1))?;
         let mut attachment: Attachment = Default::default();
         attachment.url = url.to_string();
-        seq.serialize_element(&attachment).unwrap();
+        seq.serialize_element(&attachment)?;
         seq.end()
     }
 
@@ -1375,7 +1933,7 @@ pub mod attachment_format_as_string {
     where
         D: Deserializer<'de>,
     {
-        let airtable_attachments = deserializer.deserialize_seq(AttachmentsVisitor {}).unwrap();
+        let airtable_attachments = deserializer.deserialize_seq(AttachmentsVisitor {})?;
         let mut url = String::new();
         if !airtable_attachments.is_empty() {
             url = airtable_attachments[0].url.to_string();
@@ -1514,9 +2072,7 @@ pub mod barcode_format_as_string {
     where
         D: Deserializer<'de>,
     {
-        let barcode = deserializer
-            .deserialize_struct("Barcode", BARCODEFIELDS, BarcodeVisitor)
-            .unwrap();
+        let barcode = deserializer.deserialize_struct("Barcode", BARCODEFIELDS, BarcodeVisitor)?;
         Ok(barcode.text)
     }
 }
@@ -1535,7 +2091,9 @@ pub mod deserialize_null_string {
     where
         D: Deserializer<'de>,
     {
-        let s = String::deserialize(deserializer).unwrap_or_default();
+        // Empty cells come back as null, anything else that isn't a string is
+        // an error.
+        let s = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
 
         Ok(s)
     }
@@ -1560,7 +2118,9 @@ pub mod deserialize_missing_timezone {
     {
         // Airtable can have issues with timezones, so try to deserialize to a
         // string instead for parsing
-        let mut s = String::deserialize(deserializer).unwrap_or_default();
+        let Some(mut s) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
 
         DateTime::parse_from_rfc3339(s.as_str())
             .or_else(|_| {
@@ -0,0 +1,125 @@
+//! A client for several bases, e.g. one per registry, sharing the API key and
+//! configuration.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use super::{Airtable, AirtableBuilder, AirtableError, Result};
+
+/// The most clients [`AirtableBases`] keeps. The base ids may come from
+/// requests, so the clients can't all be kept.
+const MAX_BASES: usize = 64;
+
+/// Hands out an [`Airtable`] client per base, built once from the same
+/// [`AirtableBuilder`]. Each base keeps its own rate limit.
+///
+/// At most `MAX_BASES` clients are kept, one is dropped to make room for
+/// another base. It is built again when its base is asked for, the rate limit
+/// of a base is shared by all its clients.
+///
+/// ```ignore
+/// let bases = AirtableBases::new(Airtable::builder(api_key, ""));
+/// let airtable = bases.base("appXXXXXXXXXXXXXX")?;
+/// let items = airtable.table::<Item>("items").list().await?;
+/// ```
+#[derive(Clone)]
+pub struct AirtableBases {
+    builder: AirtableBuilder,
+    clients: Arc<Mutex<HashMap<String, Arc<Airtable>>>>,
+}
+
+impl AirtableBases {
+    /// Build the clients with `builder`, whatever base it was given.
+    pub fn new(builder: AirtableBuilder) -> Self {
+        AirtableBases {
+            builder,
+            clients: Default::default(),
+        }
+    }
+
+    /// Build the clients from the environment, see
+    /// [`AirtableBuilder::from_env`]. AIRTABLE_BASE_ID is not needed.
+    pub fn from_env() -> Self {
+        Self::new(AirtableBuilder::from_env())
+    }
+
+    /// The client for `base_id`. Base ids are letters and digits, anything
+    /// else is rejected so an id taken from a request can't leave the base.
+    pub fn base(&self, base_id: &str) -> Result<Arc<Airtable>> {
+        if base_id.is_empty() || !base_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(AirtableError::Config(format!(
+                "invalid base id {base_id:?}"
+            )));
+        }
+
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(client) = clients.get(base_id) {
+            return Ok(client.clone());
+        }
+
+        let client = Arc::new(self.builder.clone().base_id(base_id).build()?);
+        if clients.len() >= MAX_BASES {
+            let dropped = clients.keys().next().cloned().unwrap_or_default();
+            clients.remove(&dropped);
+        }
+        clients.insert(base_id.to_string(), client.clone());
+
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bases() -> AirtableBases {
+        AirtableBases::new(Airtable::builder("key", ""))
+    }
+
+    #[test]
+    fn builds_a_client_once_per_base() {
+        let bases = bases();
+
+        let a = bases.base("appHouseholdA").unwrap();
+        assert_eq!(a.base_id, "appHouseholdA");
+        assert!(Arc::ptr_eq(&a, &bases.base("appHouseholdA").unwrap()));
+
+        let b = bases.base("appHouseholdB").unwrap();
+        assert_eq!(b.base_id, "appHouseholdB");
+        assert!(!Arc::ptr_eq(&a, &b));
+
+        // Clones share the clients.
+        assert!(Arc::ptr_eq(
+            &a,
+            &bases.clone().base("appHouseholdA").unwrap()
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_base_ids() {
+        let bases = bases();
+
+        for id in ["", "app/../v0", "app?x=1", "app id", "appé"] {
+            assert!(
+                matches!(bases.base(id), Err(AirtableError::Config(_))),
+                "{id:?}"
+            );
+        }
+        assert!(bases.clients.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_a_bounded_number_of_clients() {
+        let bases = bases();
+
+        for i in 0..MAX_BASES + 10 {
+            let id = format!("app{i}");
+            assert_eq!(bases.base(&id).unwrap().base_id, id);
+        }
+
+        let clients = bases.clients.lock().unwrap();
+        assert_eq!(clients.len(), MAX_BASES);
+        assert!(clients.contains_key(&format!("app{}", MAX_BASES + 9)));
+    }
+}
@@ -0,0 +1,225 @@
+//! Configuring an [`Airtable`] client: where it sends requests, how long it
+//! waits, how it retries and what runs around each request.
+use std::{env, sync::Arc};
+
+use reqwest::Url;
+use reqwest_middleware::Middleware;
+use reqwest_retry::policies::ExponentialBackoff;
+
+#[cfg(feature = "ssr")]
+use super::RateLimiter;
+use super::{api_key_from_env, Airtable, AirtableError, Result};
+
+/// Endpoint for the Airtable API.
+const DEFAULT_ENDPOINT: &str = "https://api.airtable.com/v0/";
+/// Endpoint for uploading attachment content.
+#[cfg(feature = "ssr")]
+const DEFAULT_CONTENT_ENDPOINT: &str = "https://content.airtable.com/v0/";
+
+/// Builds an [`Airtable`] client.
+///
+/// ```ignore
+/// let airtable = Airtable::builder(api_key, base_id)
+///     .endpoint("http://localhost:3001/v0/")
+///     .timeout(Duration::from_secs(10))
+///     .build()?;
+/// ```
+#[derive(Clone)]
+pub struct AirtableBuilder {
+    key: String,
+    base_id: String,
+    enterprise_account_id: String,
+    endpoint: Option<String>,
+    content_endpoint: Option<String>,
+    retry_policy: ExponentialBackoff,
+    user_agent: Option<String>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    #[cfg(feature = "ssr")]
+    timeout: Option<std::time::Duration>,
+    #[cfg(feature = "ssr")]
+    proxy: Option<reqwest::Proxy>,
+}
+
+impl AirtableBuilder {
+    pub fn new<K, B>(key: K, base_id: B) -> Self
+    where
+        K: ToString,
+        B: ToString,
+    {
+        AirtableBuilder {
+            key: key.to_string(),
+            base_id: base_id.to_string(),
+            enterprise_account_id: String::new(),
+            endpoint: None,
+            content_endpoint: None,
+            retry_policy: ExponentialBackoff::builder().build_with_max_retries(3),
+            user_agent: None,
+            middleware: Vec::new(),
+            #[cfg(feature = "ssr")]
+            timeout: None,
+            #[cfg(feature = "ssr")]
+            proxy: None,
+        }
+    }
+
+    /// A builder configured from the AIRTABLE_API_KEY, AIRTABLE_BASE_ID,
+    /// AIRTABLE_ENTERPRISE_ACCOUNT_ID and AIRTABLE_ENDPOINT env variables.
+    pub fn from_env() -> Self {
+        let base_id = env::var("AIRTABLE_BASE_ID").unwrap_or_default();
+        let enterprise_account_id = env::var("AIRTABLE_ENTERPRISE_ACCOUNT_ID").unwrap_or_default();
+
+        let builder = AirtableBuilder::new(api_key_from_env(), base_id)
+            .enterprise_account_id(enterprise_account_id);
+        match env::var("AIRTABLE_ENDPOINT") {
+            Ok(endpoint) if !endpoint.is_empty() => builder.endpoint(endpoint),
+            _ => builder,
+        }
+    }
+
+    /// The base the client sends requests to.
+    pub fn base_id<B: ToString>(mut self, base_id: B) -> Self {
+        self.base_id = base_id.to_string();
+        self
+    }
+
+    /// The enterprise account id, only needed for the enterprise API.
+    pub fn enterprise_account_id<E: ToString>(mut self, enterprise_account_id: E) -> Self {
+        self.enterprise_account_id = enterprise_account_id.to_string();
+        self
+    }
+
+    /// The base URL of the API, `https://api.airtable.com/v0/` by default. Point
+    /// it at a local stand-in to test against that instead.
+    pub fn endpoint<S: ToString>(mut self, endpoint: S) -> Self {
+        self.endpoint = Some(endpoint.to_string());
+        self
+    }
+
+    /// The base URL attachments are uploaded to. Defaults to
+    /// `https://content.airtable.com/v0/`, or to the [`endpoint`] when that was
+    /// changed.
+    ///
+    /// [`endpoint`]: AirtableBuilder::endpoint
+    pub fn content_endpoint<S: ToString>(mut self, endpoint: S) -> Self {
+        self.content_endpoint = Some(endpoint.to_string());
+        self
+    }
+
+    /// How failed requests are retried, 3 times with exponential backoff by
+    /// default. Use `build_with_max_retries(0)` to not retry.
+    pub fn retry_policy(mut self, retry_policy: ExponentialBackoff) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// The User-Agent header sent with every request.
+    pub fn user_agent<S: ToString>(mut self, user_agent: S) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Add a middleware. Middleware runs in the order it was added, after the
+    /// tracing and retries and before the rate limiter, so it sees every retry.
+    pub fn middleware<M: Middleware>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// How long a request may take, including reading the response. There is
+    /// no timeout by default.
+    #[cfg(feature = "ssr")]
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Send the requests through a proxy.
+    #[cfg(feature = "ssr")]
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Build the client.
+    pub fn build(self) -> Result<Airtable> {
+        let endpoint = parse_endpoint(self.endpoint.as_deref().unwrap_or(DEFAULT_ENDPOINT))?;
+        #[cfg(feature = "ssr")]
+        let content_endpoint = match (&self.content_endpoint, &self.endpoint) {
+            (Some(content_endpoint), _) => parse_endpoint(content_endpoint)?,
+            (None, Some(_)) => endpoint.clone(),
+            (None, None) => parse_endpoint(DEFAULT_CONTENT_ENDPOINT)?,
+        };
+
+        let mut http = reqwest::Client::builder();
+        if let Some(user_agent) = &self.user_agent {
+            http = http.user_agent(user_agent);
+        }
+        #[cfg(feature = "ssr")]
+        {
+            if let Some(timeout) = self.timeout {
+                http = http.timeout(timeout);
+            }
+            if let Some(proxy) = self.proxy {
+                http = http.proxy(proxy);
+            }
+        }
+        let http = http
+            .build()
+            .map_err(|e| AirtableError::Config(format!("creating client failed: {e}")))?;
+
+        let mut builder = reqwest_middleware::ClientBuilder::new(http.clone())
+            // Trace HTTP requests. See the tracing crate to make use of these traces.
+            .with(reqwest_tracing::TracingMiddleware::default())
+            // Retry failed requests.
+            .with(reqwest_retry::RetryTransientMiddleware::new_with_policy(
+                self.retry_policy,
+            ));
+        for middleware in &self.middleware {
+            builder = builder.with_arc(middleware.clone());
+        }
+        // Stay under the per base rate limit. This runs for every retry, so a
+        // request retried after a 429 waits for the lockout to end.
+        #[cfg(feature = "ssr")]
+        let builder = builder.with(RateLimiter::for_base(&self.base_id));
+        let client = builder.build();
+
+        // The retry middleware needs to clone the request, which a streamed
+        // body can't be, and resending a create that timed out may create the
+        // records twice, so those go through a client without it.
+        let once_client = {
+            let mut builder = reqwest_middleware::ClientBuilder::new(http)
+                .with(reqwest_tracing::TracingMiddleware::default());
+            for middleware in &self.middleware {
+                builder = builder.with_arc(middleware.clone());
+            }
+            #[cfg(feature = "ssr")]
+            let builder = builder.with(RateLimiter::for_base(&self.base_id));
+            builder.build()
+        };
+
+        Ok(Airtable {
+            key: self.key,
+            base_id: self.base_id,
+            enterprise_account_id: self.enterprise_account_id,
+            endpoint,
+            #[cfg(feature = "ssr")]
+            content_endpoint,
+
+            client,
+            once_client,
+        })
+    }
+}
+
+/// Parse an endpoint, making sure paths are joined onto it rather than
+/// replacing its last segment.
+fn parse_endpoint(endpoint: &str) -> Result<Url> {
+    let endpoint = if endpoint.ends_with('/') {
+        endpoint.to_string()
+    } else {
+        format!("{endpoint}/")
+    };
+
+    Url::parse(&endpoint)
+        .map_err(|e| AirtableError::Config(format!("invalid endpoint {endpoint}: {e}")))
+}
@@ -0,0 +1,492 @@
+//! Bulk writes with bounded concurrency and per chunk results.
+//!
+//! [`Airtable::create_records`] and friends send their chunks of 10 records one
+//! after another and stop at the first error, leaving the earlier chunks
+//! committed. A [`BulkWriter`] sends several chunks at once and reports the
+//! outcome of every chunk, so a failed import can be retried with just the
+//! records that did not make it.
+//!
+//! ```ignore
+//! let report = airtable
+//!     .bulk_writer("items")
+//!     .concurrency(3)
+//!     .upsert(records, &["name"])
+//!     .await;
+//!
+//! if !report.is_complete() {
+//!     let retry = report.into_failed_records();
+//! }
+//! ```
+use std::future::Future;
+
+use futures::{stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{Airtable, AirtableError, Method, Record, Result, Upserted};
+
+/// The number of chunks sent at once by default.
+pub const DEFAULT_CONCURRENCY: usize = 5;
+
+/// Writes records to a table in chunks of 10, several chunks at a time.
+///
+/// Requests still go through the client's rate limiter, so a higher
+/// concurrency only helps while Airtable is slower than the rate limit.
+pub struct BulkWriter<'a> {
+    airtable: &'a Airtable,
+    table: String,
+    concurrency: usize,
+}
+
+impl<'a> BulkWriter<'a> {
+    pub fn new(airtable: &'a Airtable, table: &str) -> Self {
+        Self {
+            airtable,
+            table: table.to_string(),
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// Set how many chunks may be in flight at once. At least one is always sent.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Create records.
+    ///
+    /// Unlike the other writes a failed create is not retried, since a request
+    /// that timed out may still have created its records. Their chunk is
+    /// reported as [`ChunkOutcome::Unknown`], and creating them again may
+    /// create them twice; use [`BulkWriter::upsert`] when the records have a
+    /// unique field.
+    pub async fn create<T>(&self, records: Vec<Record<T>>) -> BulkReport<T>
+    where
+        T: Serialize + DeserializeOwned + Clone,
+    {
+        self.write(records, |chunk| async move {
+            let records = self
+                .airtable
+                .create_records_inner(&self.table, chunk)
+                .await?;
+            Ok(Upserted {
+                created_records: records.iter().map(|r| r.id.to_string()).collect(),
+                updated_records: Vec::new(),
+                records,
+            })
+        })
+        .await
+    }
+
+    /// Update records, changing only the fields that are serialized.
+    pub async fn update<T>(&self, records: Vec<Record<T>>) -> BulkReport<T>
+    where
+        T: Serialize + DeserializeOwned + Clone,
+    {
+        self.update_with(Method::PATCH, records).await
+    }
+
+    /// Replace records, clearing every field that is not serialized.
+    pub async fn replace<T>(&self, records: Vec<Record<T>>) -> BulkReport<T>
+    where
+        T: Serialize + DeserializeOwned + Clone,
+    {
+        self.update_with(Method::PUT, records).await
+    }
+
+    async fn update_with<T>(&self, method: Method, records: Vec<Record<T>>) -> BulkReport<T>
+    where
+        T: Serialize + DeserializeOwned + Clone,
+    {
+        self.write(records, |chunk| {
+            let method = method.clone();
+            async move {
+                let records = self
+                    .airtable
+                    .update_records_inner(method, &self.table, chunk)
+                    .await?;
+                Ok(Upserted {
+                    created_records: Vec::new(),
+                    updated_records: records.iter().map(|r| r.id.to_string()).collect(),
+                    records,
+                })
+            }
+        })
+        .await
+    }
+
+    /// Create or update records, matching existing records on the `merge_on` fields.
+    pub async fn upsert<T>(&self, records: Vec<Record<T>>, merge_on: &[&str]) -> BulkReport<T>
+    where
+        T: Serialize + DeserializeOwned + Clone,
+    {
+        self.write(records, |chunk| {
+            self.airtable
+                .upsert_records_inner(&self.table, chunk, merge_on)
+        })
+        .await
+    }
+
+    /// Send `records` in chunks of 10 through `write`, `concurrency` chunks at a time.
+    async fn write<T, F, Fut>(&self, records: Vec<Record<T>>, write: F) -> BulkReport<T>
+    where
+        T: Clone,
+        F: Fn(Vec<Record<T>>) -> Fut,
+        Fut: Future<Output = Result<Upserted<T>>>,
+    {
+        let chunks: Vec<Vec<Record<T>>> = records.chunks(10).map(|c| c.to_vec()).collect();
+
+        let mut chunks: Vec<ChunkReport<T>> = stream::iter(chunks.into_iter().enumerate())
+            .map(|(index, chunk)| {
+                let written = write(chunk.clone());
+                async move {
+                    let outcome = match written.await {
+                        Ok(written) => ChunkOutcome::Succeeded(written),
+                        // Only a response Airtable accepted is deserialized.
+                        Err(error @ AirtableError::Deserialize { .. }) => {
+                            log::warn!(
+                                "[airtable-api] Bulk write of chunk {index} succeeded but its \
+                                 response could not be read: {error}"
+                            );
+                            ChunkOutcome::Unparsed {
+                                records: chunk,
+                                error,
+                            }
+                        }
+                        Err(error) if may_have_been_written(&error) => {
+                            log::warn!(
+                                "[airtable-api] Bulk write of chunk {index} may have failed: {error}"
+                            );
+                            ChunkOutcome::Unknown {
+                                records: chunk,
+                                error,
+                            }
+                        }
+                        Err(error) => {
+                            log::warn!(
+                                "[airtable-api] Bulk write of chunk {index} failed: {error}"
+                            );
+                            ChunkOutcome::Failed {
+                                records: chunk,
+                                error,
+                            }
+                        }
+                    };
+
+                    ChunkReport { index, outcome }
+                }
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        chunks.sort_by_key(|c| c.index);
+
+        BulkReport { chunks }
+    }
+}
+
+/// Whether a write that failed may still have reached Airtable: the request
+/// was sent but no response came back, e.g. it timed out.
+fn may_have_been_written(error: &AirtableError) -> bool {
+    match error {
+        AirtableError::Transport(reqwest_middleware::Error::Reqwest(e)) => {
+            !(e.is_connect() || e.is_builder())
+        }
+        AirtableError::Transport(reqwest_middleware::Error::Middleware(_)) => true,
+        _ => false,
+    }
+}
+
+/// The outcome of every chunk of a bulk write, in the order the records were given.
+#[derive(Debug)]
+pub struct BulkReport<T> {
+    pub chunks: Vec<ChunkReport<T>>,
+}
+
+/// The outcome of writing a single chunk of at most 10 records.
+#[derive(Debug)]
+pub struct ChunkReport<T> {
+    /// The position of the chunk, chunk `i` holds records `10 * i..10 * i + 10`.
+    pub index: usize,
+    pub outcome: ChunkOutcome<T>,
+}
+
+#[derive(Debug)]
+pub enum ChunkOutcome<T> {
+    /// Airtable accepted the chunk. For creates every id is in
+    /// `created_records`, for updates every id is in `updated_records`.
+    Succeeded(Upserted<T>),
+    /// Airtable wrote the chunk but the records it returned don't deserialize
+    /// into `T`. `records` are the records as they were sent, they must not
+    /// be written again.
+    Unparsed {
+        records: Vec<Record<T>>,
+        error: AirtableError,
+    },
+    /// The request was sent but no response came back, e.g. it timed out.
+    /// Its records may or may not have been written, check before writing
+    /// them again.
+    Unknown {
+        records: Vec<Record<T>>,
+        error: AirtableError,
+    },
+    /// Airtable rejected the chunk, or it could not be sent, and none of its
+    /// records were written.
+    Failed {
+        records: Vec<Record<T>>,
+        error: AirtableError,
+    },
+}
+
+impl<T> BulkReport<T> {
+    /// Returns true if every chunk was written, whether or not its response
+    /// could be read.
+    pub fn is_complete(&self) -> bool {
+        self.chunks.iter().all(|c| {
+            matches!(
+                c.outcome,
+                ChunkOutcome::Succeeded(_) | ChunkOutcome::Unparsed { .. }
+            )
+        })
+    }
+
+    /// The records that were written, as Airtable returned them. Chunks whose
+    /// response could not be read are left out.
+    pub fn succeeded(&self) -> impl Iterator<Item = &Record<T>> {
+        self.chunks.iter().flat_map(|c| match &c.outcome {
+            ChunkOutcome::Succeeded(written) => written.records.as_slice(),
+            ChunkOutcome::Unparsed { .. }
+            | ChunkOutcome::Unknown { .. }
+            | ChunkOutcome::Failed { .. } => &[],
+        })
+    }
+
+    /// The errors of the chunks that failed, may have failed or whose
+    /// response could not be read, with their index.
+    pub fn errors(&self) -> impl Iterator<Item = (usize, &AirtableError)> {
+        self.chunks.iter().filter_map(|c| match &c.outcome {
+            ChunkOutcome::Unparsed { error, .. }
+            | ChunkOutcome::Unknown { error, .. }
+            | ChunkOutcome::Failed { error, .. } => Some((c.index, error)),
+            ChunkOutcome::Succeeded(_) => None,
+        })
+    }
+
+    /// The records that were certainly not written, ready to be retried.
+    /// Those of [`ChunkOutcome::Unknown`] chunks are left out, see
+    /// [`BulkReport::into_unknown_records`].
+    pub fn into_failed_records(self) -> Vec<Record<T>> {
+        self.chunks
+            .into_iter()
+            .flat_map(|c| match c.outcome {
+                ChunkOutcome::Failed { records, .. } => records,
+                ChunkOutcome::Succeeded(_)
+                | ChunkOutcome::Unparsed { .. }
+                | ChunkOutcome::Unknown { .. } => Vec::new(),
+            })
+            .collect()
+    }
+
+    /// The records that may or may not have been written, to be checked
+    /// before they are written again.
+    pub fn into_unknown_records(self) -> Vec<Record<T>> {
+        self.chunks
+            .into_iter()
+            .flat_map(|c| match c.outcome {
+                ChunkOutcome::Unknown { records, .. } => records,
+                ChunkOutcome::Succeeded(_)
+                | ChunkOutcome::Unparsed { .. }
+                | ChunkOutcome::Failed { .. } => Vec::new(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use std::{net::TcpListener, time::Duration};
+
+    use axum::{routing::post, Router};
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::airtable::{fake::FakeAirtable, AirtableBuilder};
+
+    /// Serve `n` numbered records and return a client for them.
+    fn serve(n: usize) -> (FakeAirtable, Airtable) {
+        let records: Vec<Value> = (0..n)
+            .map(|i| json!({ "id": format!("rec{i:014}"), "fields": { "n": i } }))
+            .collect();
+        let fixture = json!({ "tables": [{ "name": "numbers", "records": records }] });
+        let fake =
+            FakeAirtable::from_fixture("appBulkTest", serde_json::from_value(fixture).unwrap())
+                .unwrap();
+        let addr = fake.spawn("127.0.0.1:0".parse().unwrap()).unwrap();
+        let airtable = fake.client(addr).unwrap();
+        (fake, airtable)
+    }
+
+    fn numbers(range: std::ops::Range<usize>) -> Vec<Record<Value>> {
+        range
+            .map(|i| Record {
+                id: format!("rec{i:014}"),
+                fields: json!({ "n": i }),
+                created_time: None,
+                warnings: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn outcomes<T>(report: &BulkReport<T>) -> Vec<&'static str> {
+        report
+            .chunks
+            .iter()
+            .map(|c| match c.outcome {
+                ChunkOutcome::Succeeded(_) => "succeeded",
+                ChunkOutcome::Unparsed { .. } => "unparsed",
+                ChunkOutcome::Unknown { .. } => "unknown",
+                ChunkOutcome::Failed { .. } => "failed",
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reports_chunks_in_input_order() {
+        let (_, airtable) = serve(0);
+        let writer = airtable.bulk_writer("numbers").concurrency(5);
+
+        // The first chunks finish last.
+        let report = writer
+            .write(numbers(0..45), |chunk| async move {
+                let first = chunk[0].fields["n"].as_u64().unwrap();
+                tokio::time::sleep(Duration::from_millis(50 - first)).await;
+                Ok(Upserted {
+                    records: chunk,
+                    ..Default::default()
+                })
+            })
+            .await;
+
+        let indexes: Vec<usize> = report.chunks.iter().map(|c| c.index).collect();
+        assert_eq!(indexes, [0, 1, 2, 3, 4]);
+        let n: Vec<u64> = report
+            .succeeded()
+            .map(|r| r.fields["n"].as_u64().unwrap())
+            .collect();
+        assert_eq!(n, (0..45).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn creates_in_input_order() {
+        let (fake, airtable) = serve(0);
+        let mut records = numbers(0..25);
+        records.iter_mut().for_each(|r| r.id.clear());
+
+        let report = airtable.bulk_writer("numbers").create(records).await;
+        assert!(report.is_complete());
+        let n: Vec<u64> = report
+            .succeeded()
+            .map(|r| r.fields["n"].as_u64().unwrap())
+            .collect();
+        assert_eq!(n, (0..25).collect::<Vec<_>>());
+        assert_eq!(fake.snapshot().tables[0].records.len(), 25);
+    }
+
+    #[tokio::test]
+    async fn reports_a_failed_chunk() {
+        let (fake, airtable) = serve(25);
+        let mut records = numbers(0..25);
+        for record in &mut records {
+            record.fields["n"] = json!(100);
+        }
+        // The fake updates nothing in a chunk with an unknown record.
+        records[15].id = "recMissing0000000".to_string();
+
+        let report = airtable.bulk_writer("numbers").update(records).await;
+        assert_eq!(outcomes(&report), ["succeeded", "failed", "succeeded"]);
+        assert!(!report.is_complete());
+        assert_eq!(report.succeeded().count(), 15);
+        let errors: Vec<usize> = report.errors().map(|(i, _)| i).collect();
+        assert_eq!(errors, [1]);
+
+        let failed = report.into_failed_records();
+        assert_eq!(failed.len(), 10);
+        assert_eq!(failed[0].id, format!("rec{:014}", 10));
+
+        let written: Vec<u64> = fake.snapshot().tables[0]
+            .records
+            .iter()
+            .map(|r| r.fields["n"].as_u64().unwrap())
+            .collect();
+        assert!(written[..10].iter().all(|n| *n == 100));
+        assert_eq!(written[10..20], (10..20).collect::<Vec<_>>());
+        assert!(written[20..].iter().all(|n| *n == 100));
+    }
+
+    #[tokio::test]
+    async fn reports_unreadable_responses() {
+        #[derive(Debug, Clone, Serialize, Deserialize)]
+        struct Number {
+            // Written as `n`, but read back from a field Airtable doesn't send.
+            #[serde(rename(serialize = "n", deserialize = "number"))]
+            n: u64,
+        }
+
+        let (fake, airtable) = serve(0);
+        let records = (0..3)
+            .map(|n| Record {
+                id: String::new(),
+                fields: Number { n },
+                created_time: None,
+                warnings: Vec::new(),
+            })
+            .collect();
+
+        let report = airtable.bulk_writer("numbers").create(records).await;
+        assert_eq!(outcomes(&report), ["unparsed"]);
+        assert!(report.is_complete());
+        assert!(report.into_failed_records().is_empty());
+        assert_eq!(fake.snapshot().tables[0].records.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn creates_without_a_response_are_unknown() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route(
+            "/v0/appBulkTimeout/numbers",
+            post(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                "{}"
+            }),
+        );
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+
+        let airtable = AirtableBuilder::new("key", "appBulkTimeout")
+            .endpoint(format!("http://{addr}/v0/"))
+            .timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+        let report = airtable.bulk_writer("numbers").create(numbers(0..5)).await;
+        assert_eq!(outcomes(&report), ["unknown"]);
+        assert!(!report.is_complete());
+        assert!(report.into_failed_records().is_empty());
+
+        // Nothing listens, so nothing was sent.
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let airtable = AirtableBuilder::new("key", "appBulkTimeout")
+            .endpoint(format!("http://{closed}/v0/"))
+            .build()
+            .unwrap();
+        let report = airtable.bulk_writer("numbers").create(numbers(0..5)).await;
+        assert_eq!(outcomes(&report), ["failed"]);
+        assert_eq!(report.into_failed_records().len(), 5);
+    }
+}
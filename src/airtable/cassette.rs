@@ -0,0 +1,475 @@
+//! Record real Airtable responses to a JSON cassette and replay them later, so
+//! tests can pin the shapes the API really returns without a network or an
+//! API key.
+//!
+//! Record once against a real base, then commit the cassette:
+//!
+//! ```ignore
+//! let airtable = Airtable::builder(api_key, base_id)
+//!     .middleware(Cassette::from_env("tests/cassettes/list_items.json")?)
+//!     .build()?;
+//! ```
+//!
+//! With `AIRTABLE_CASSETTE=record` every request is sent and the cassette
+//! rewritten, otherwise the requests are answered from the cassette and one
+//! missing from it fails. The values of the `Authorization` and
+//! `Proxy-Authorization` headers are written as `[REDACTED]`.
+//!
+//! [`AirtableBuilder::middleware`](super::AirtableBuilder::middleware) puts
+//! the cassette inside the retry middleware, so every attempt is its own
+//! interaction: a request retried after a 429 is recorded as the 429 and then
+//! the response to the retry. Replaying answers them in that order, so the
+//! retry and its backoff happen again. Record again, or remove the failed
+//! attempts from the cassette, to keep replays quick.
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use reqwest::{Request, Response, ResponseBuilderExt, Url};
+use reqwest_middleware::{Middleware, Next};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use task_local_extensions::Extensions;
+
+/// Headers whose values are replaced before a cassette is written.
+const REDACTED_HEADERS: &[&str] = &["authorization", "proxy-authorization"];
+/// Response headers not recorded, they no longer match the body on replay.
+const SKIPPED_HEADERS: &[&str] = &["content-length", "content-encoding", "transfer-encoding"];
+
+/// Whether a [`Cassette`] records or replays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Send the requests and write what was exchanged to the cassette.
+    Record,
+    /// Answer the requests from the cassette without sending them.
+    Replay,
+}
+
+/// A middleware recording or replaying the requests of a client.
+///
+/// Requests are replayed in the order they were recorded: each one is answered
+/// by the first unplayed interaction with the same method, path, query and
+/// body, whatever the endpoint.
+#[derive(Debug, Clone)]
+pub struct Cassette {
+    path: PathBuf,
+    mode: CassetteMode,
+    tape: Arc<Mutex<Tape>>,
+}
+
+#[derive(Debug, Default)]
+struct Tape {
+    interactions: Vec<Interaction>,
+    played: Vec<bool>,
+}
+
+/// An error recording or replaying a cassette.
+#[derive(Debug, thiserror::Error)]
+pub enum CassetteError {
+    /// The request was not recorded in the cassette.
+    #[error("no unplayed interaction for {method} {url} in cassette {path}")]
+    NotRecorded {
+        method: String,
+        url: String,
+        path: PathBuf,
+    },
+    /// The cassette could not be read or written.
+    #[error("cassette {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl Cassette {
+    /// Record to the cassette at `path`, replacing what it held.
+    pub fn record(path: impl AsRef<Path>) -> Self {
+        Cassette {
+            path: path.as_ref().to_path_buf(),
+            mode: CassetteMode::Record,
+            tape: Default::default(),
+        }
+    }
+
+    /// Replay the cassette at `path`.
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self, CassetteError> {
+        let path = path.as_ref().to_path_buf();
+        let file: CassetteFile = fs::read(&path)
+            .and_then(|bytes| serde_json::from_slice(&bytes).map_err(io::Error::from))
+            .map_err(|source| CassetteError::Io {
+                path: path.clone(),
+                source,
+            })?;
+
+        Ok(Cassette {
+            path,
+            mode: CassetteMode::Replay,
+            tape: Arc::new(Mutex::new(Tape {
+                played: vec![false; file.interactions.len()],
+                interactions: file.interactions,
+            })),
+        })
+    }
+
+    /// Record when the AIRTABLE_CASSETTE env variable is `record`, replay
+    /// otherwise.
+    pub fn from_env(path: impl AsRef<Path>) -> Result<Self, CassetteError> {
+        match env::var("AIRTABLE_CASSETTE").as_deref() {
+            Ok("record") => Ok(Self::record(path)),
+            _ => Self::replay(path),
+        }
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    /// The number of recorded interactions not replayed yet, to check a test
+    /// made every request it recorded.
+    pub fn unplayed(&self) -> usize {
+        self.tape().played.iter().filter(|p| !**p).count()
+    }
+
+    fn tape(&self) -> std::sync::MutexGuard<'_, Tape> {
+        self.tape.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Add an interaction and rewrite the cassette.
+    fn push(&self, interaction: Interaction) -> Result<(), CassetteError> {
+        let mut tape = self.tape();
+        tape.interactions.push(interaction);
+        tape.played.push(true);
+
+        let file = CassetteFile {
+            interactions: tape.interactions.clone(),
+        };
+        let write = || {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&self.path, serde_json::to_vec_pretty(&file)?)
+        };
+        write().map_err(|source| CassetteError::Io {
+            path: self.path.clone(),
+            source,
+        })
+    }
+
+    /// Take the response recorded for a request.
+    fn play(&self, request: &RecordedRequest) -> Result<RecordedResponse, CassetteError> {
+        let mut tape = self.tape();
+        let Tape {
+            interactions,
+            played,
+        } = &mut *tape;
+
+        let i = interactions
+            .iter()
+            .zip(played.iter())
+            .position(|(interaction, played)| !played && interaction.request.matches(request))
+            .ok_or_else(|| CassetteError::NotRecorded {
+                method: request.method.to_string(),
+                url: request.url.to_string(),
+                path: self.path.clone(),
+            })?;
+        played[i] = true;
+
+        Ok(interactions[i].response.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Cassette {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let request = RecordedRequest::new(&req);
+
+        match self.mode {
+            CassetteMode::Replay => {
+                let response = self
+                    .play(&request)
+                    .map_err(reqwest_middleware::Error::middleware)?;
+                response
+                    .into_response(req.url().clone())
+                    .map_err(reqwest_middleware::Error::middleware)
+            }
+            CassetteMode::Record => {
+                let resp = next.run(req, extensions).await?;
+                let url = resp.url().clone();
+                let response = RecordedResponse::new(resp).await?;
+
+                self.push(Interaction {
+                    request,
+                    response: response.clone(),
+                })
+                .map_err(reqwest_middleware::Error::middleware)?;
+
+                response
+                    .into_response(url)
+                    .map_err(reqwest_middleware::Error::middleware)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<Value>,
+}
+
+impl RecordedRequest {
+    fn new(req: &Request) -> Self {
+        RecordedRequest {
+            method: req.method().to_string(),
+            url: req.url().to_string(),
+            headers: record_headers(req.headers()),
+            body: req.body().and_then(|b| b.as_bytes()).map(record_body),
+        }
+    }
+
+    /// Whether this is the same request, sent to any endpoint.
+    fn matches(&self, other: &RecordedRequest) -> bool {
+        let target = |url: &str| {
+            Url::parse(url)
+                .map(|u| format!("{}?{}", u.path(), u.query().unwrap_or_default()))
+                .unwrap_or_else(|_| url.to_string())
+        };
+
+        self.method == other.method
+            && target(&self.url) == target(&other.url)
+            && self.body == other.body
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<Value>,
+}
+
+impl RecordedResponse {
+    async fn new(resp: Response) -> reqwest_middleware::Result<Self> {
+        let status = resp.status().as_u16();
+        let mut headers = record_headers(resp.headers());
+        headers.retain(|name, _| !SKIPPED_HEADERS.contains(&name.as_str()));
+        let body = resp.bytes().await?;
+
+        Ok(RecordedResponse {
+            status,
+            headers,
+            body: (!body.is_empty()).then(|| record_body(&body)),
+        })
+    }
+
+    fn into_response(self, url: Url) -> Result<Response, http::Error> {
+        let mut builder = http::Response::builder().status(self.status).url(url);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+
+        let body = match self.body {
+            None => Vec::new(),
+            Some(Value::String(text)) => text.into_bytes(),
+            Some(value) => value.to_string().into_bytes(),
+        };
+
+        Ok(builder.body(body)?.into())
+    }
+}
+
+fn record_headers(headers: &reqwest::header::HeaderMap) -> BTreeMap<String, String> {
+    let mut recorded: BTreeMap<String, String> = BTreeMap::new();
+    for (name, value) in headers {
+        let value = match REDACTED_HEADERS.contains(&name.as_str()) {
+            true => "[REDACTED]".to_string(),
+            false => String::from_utf8_lossy(value.as_bytes()).to_string(),
+        };
+        recorded
+            .entry(name.to_string())
+            .and_modify(|v| {
+                v.push_str(", ");
+                v.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    recorded
+}
+
+/// JSON bodies are kept as JSON so cassettes are readable, anything else as
+/// text.
+fn record_body(body: &[u8]) -> Value {
+    serde_json::from_slice(body)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use axum::{routing::get, Router};
+    use chrono::{TimeZone, Utc};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::airtable::{Airtable, ListOptions};
+
+    const LIST_ITEMS: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/cassettes/list_items.json"
+    );
+    const ENTERPRISE_USERS: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/cassettes/enterprise_users.json"
+    );
+
+    fn airtable(cassette: &Cassette) -> Airtable {
+        Airtable::builder("keyCassette", "appCassette000001")
+            .enterprise_account_id("entCassette000001")
+            .middleware(cassette.clone())
+            .build()
+            .unwrap()
+    }
+
+    fn options() -> ListOptions {
+        ListOptions {
+            page_size: Some(2),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_list_records() {
+        let cassette = Cassette::replay(LIST_ITEMS).unwrap();
+        let airtable = airtable(&cassette);
+
+        let records = airtable
+            .list_records::<Value>("items", "Grid view", &options())
+            .await
+            .unwrap();
+        let ids: Vec<_> = records.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            ["recItem000000001", "recItem000000002", "recItem000000004"]
+        );
+        assert_eq!(records[1].fields["name"], "yoga mat");
+        assert_eq!(cassette.unplayed(), 0);
+
+        // Every interaction was played, the same requests now fail.
+        assert!(airtable
+            .list_records::<Value>("items", "Grid view", &options())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn replays_pages() {
+        let cassette = Cassette::replay(LIST_ITEMS).unwrap();
+        let airtable = airtable(&cassette);
+
+        let mut pages = airtable.pages::<Value>("items", "Grid view", options());
+        let mut sizes = Vec::new();
+        while let Some(page) = pages.next_page().await.unwrap() {
+            sizes.push(page.len());
+        }
+        assert_eq!(sizes, [2, 1]);
+        assert_eq!(cassette.unplayed(), 0);
+    }
+
+    #[tokio::test]
+    async fn replays_enterprise_calls() {
+        // The cassette only holds the meta API urls, so this also pins the
+        // enterprise endpoints.
+        let cassette = Cassette::replay(ENTERPRISE_USERS).unwrap();
+        let airtable = airtable(&cassette);
+
+        let users = airtable.list_users().await.unwrap();
+        let emails: Vec<_> = users.iter().map(|u| u.email.as_str()).collect();
+        assert_eq!(emails, ["ada@example.com", "grace@example.com"]);
+
+        let user = airtable
+            .get_enterprise_user("ada@example.com")
+            .await
+            .unwrap();
+        assert_eq!(user.id, "usrAda0000000001");
+        assert_eq!(user.invited_to_airtable_by_user_id, "");
+        // Sent without a timezone, read as UTC.
+        assert_eq!(
+            user.last_activity_time,
+            Some(Utc.with_ymd_and_hms(2023, 10, 2, 9, 30, 0).unwrap())
+        );
+        assert_eq!(user.collaborations.workspace_collaborations.len(), 1);
+        assert_eq!(
+            user.collaborations.base_collaborations[0].base_id,
+            "appCassette000001"
+        );
+
+        airtable
+            .delete_internal_user_by_email("ada@example.com")
+            .await
+            .unwrap();
+        assert_eq!(cassette.unplayed(), 0);
+    }
+
+    #[tokio::test]
+    async fn redacts_the_authorization_header() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/", get(|| async { r#"{"ok":true}"# }));
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+
+        let path = env::temp_dir().join(format!("cassette-{}.json", std::process::id()));
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(Cassette::record(&path))
+            .build();
+        let resp = client
+            .get(format!("http://{addr}/"))
+            .bearer_auth("keySecret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.text().await.unwrap(), r#"{"ok":true}"#);
+
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(written.contains(r#""authorization": "[REDACTED]""#));
+        assert!(!written.contains("keySecret"));
+
+        // The response was recorded as JSON.
+        let file: CassetteFile = serde_json::from_str(&written).unwrap();
+        assert_eq!(file.interactions.len(), 1);
+        assert_eq!(
+            file.interactions[0].response.body,
+            Some(serde_json::json!({"ok": true}))
+        );
+    }
+}
@@ -0,0 +1,530 @@
+//! Generate Rust record types from the schema of a base.
+//!
+//! Every table becomes a struct with one `Option` per field, renamed to the
+//! Airtable field name and typed after the field type, so it can be used with
+//! [`Airtable::list_records`](super::Airtable::list_records) directly. Computed
+//! fields such as formulas are never serialized, since Airtable rejects writes
+//! to them.
+//!
+//! The `airtable-codegen` binary runs this against the base in the environment.
+use std::{collections::HashSet, fmt::Write};
+
+use super::{FieldOptions, FieldSchema, FieldType, TableSchema};
+
+/// Rust keywords that cannot be used as field names.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "macro",
+    "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// Generate a module with one struct per table.
+pub fn generate(base_id: &str, tables: &[TableSchema]) -> String {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "// Generated by airtable-codegen from base {base_id}. Do not edit by hand."
+    )
+    .unwrap();
+    out.push_str(
+        "#![allow(unused_imports)]\n\n\
+         use chrono::{DateTime, NaiveDate, Utc};\n\
+         use schemars::JsonSchema;\n\
+         use serde::{Deserialize, Serialize};\n\n\
+         use crate::airtable::{\n    \
+             AiText, Attachment, Barcode, Button, Checkbox, Computed, CreatedBy, Currency, Duration,\n    \
+             LastModifiedBy, LastModifiedTime, Lookup, MultipleSelects, Percent, Rating,\n    \
+             RecordLinks, RichText, Rollup, SingleSelect, User,\n\
+         };\n",
+    );
+
+    // Tables whose names only differ in punctuation or case get a number.
+    let mut seen = HashSet::new();
+    for table in tables {
+        let mut ident = type_name(&table.name);
+        let mut n = 2;
+        while !seen.insert(ident.clone()) {
+            ident = format!("{}{n}", type_name(&table.name));
+            n += 1;
+        }
+
+        out.push('\n');
+        out.push_str(&generate_struct(table, &ident));
+    }
+
+    out
+}
+
+/// Generate the struct for a single table.
+pub fn generate_table(table: &TableSchema) -> String {
+    generate_struct(table, &type_name(&table.name))
+}
+
+fn generate_struct(table: &TableSchema, ident: &str) -> String {
+    let mut out = String::new();
+
+    let fallback = format!("A record in the `{}` table.", table.name);
+    write_doc(&mut out, "", table.description.as_deref(), &fallback);
+    out.push_str("#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]\n");
+    writeln!(out, "pub struct {ident} {{").unwrap();
+
+    let mut seen = HashSet::new();
+    for field in &table.fields {
+        let mut ident = field_name(&field.name);
+        let mut n = 2;
+        while !seen.insert(ident.clone()) {
+            ident = format!("{}_{n}", field_name(&field.name));
+            n += 1;
+        }
+
+        let fallback = format!("The `{}` field.", field.name);
+        write_doc(&mut out, "    ", field.description.as_deref(), &fallback);
+        let skip = if is_computed(field.type_) {
+            "skip_serializing"
+        } else {
+            "skip_serializing_if = \"Option::is_none\""
+        };
+        writeln!(
+            out,
+            "    #[serde(default, {skip}, rename = {:?})]",
+            field.name
+        )
+        .unwrap();
+        writeln!(out, "    pub {ident}: Option<{}>,", rust_type(field)).unwrap();
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+/// Write the Airtable description as a doc comment, or `fallback` without one.
+fn write_doc(out: &mut String, indent: &str, description: Option<&str>, fallback: &str) {
+    match description.filter(|d| !d.trim().is_empty()) {
+        Some(description) => {
+            for line in description.lines() {
+                writeln!(out, "{indent}/// {}", line.trim_end()).unwrap();
+            }
+        }
+        None => writeln!(out, "{indent}/// {fallback}").unwrap(),
+    }
+}
+
+/// The Rust type of a field's values.
+pub fn rust_type(field: &FieldSchema) -> String {
+    value_type(field.type_, field.options.as_ref())
+}
+
+fn value_type(type_: FieldType, options: Option<&FieldOptions>) -> String {
+    let precision = options.and_then(|o| o.precision);
+
+    match type_ {
+        FieldType::SingleLineText
+        | FieldType::Email
+        | FieldType::Url
+        | FieldType::MultilineText
+        | FieldType::PhoneNumber => "String".to_string(),
+        FieldType::AiText => "AiText".to_string(),
+        FieldType::RichText => "RichText".to_string(),
+        FieldType::SingleSelect => "SingleSelect".to_string(),
+        FieldType::Number if precision == Some(0) => "i64".to_string(),
+        FieldType::Number => "f64".to_string(),
+        FieldType::Percent => "Percent".to_string(),
+        FieldType::Currency => "Currency".to_string(),
+        FieldType::Duration => "Duration".to_string(),
+        FieldType::Rating => "Rating".to_string(),
+        FieldType::Count | FieldType::AutoNumber => "i64".to_string(),
+        FieldType::Checkbox => "Checkbox".to_string(),
+        FieldType::MultipleSelects => "MultipleSelects".to_string(),
+        FieldType::MultipleRecordLinks => "RecordLinks".to_string(),
+        FieldType::Date => "NaiveDate".to_string(),
+        FieldType::DateTime | FieldType::CreatedTime => "DateTime<Utc>".to_string(),
+        FieldType::LastModifiedTime => "LastModifiedTime".to_string(),
+        FieldType::MultipleAttachments => "Vec<Attachment>".to_string(),
+        FieldType::SingleCollaborator => "User".to_string(),
+        FieldType::CreatedBy => "CreatedBy".to_string(),
+        FieldType::LastModifiedBy => "LastModifiedBy".to_string(),
+        FieldType::MultipleCollaborators => "Vec<User>".to_string(),
+        FieldType::Barcode => "Barcode".to_string(),
+        FieldType::Button => "Button".to_string(),
+        // Formulas and rollups hold an error object when they fail.
+        FieldType::Formula => match options.and_then(|o| o.result.as_deref()) {
+            Some(result) => format!(
+                "Computed<{}>",
+                value_type(result.type_, result.options.as_ref())
+            ),
+            None => "serde_json::Value".to_string(),
+        },
+        // A rollup aggregates to a single value, a lookup lists the linked values.
+        FieldType::Rollup => match options.and_then(|o| o.result.as_deref()) {
+            Some(result) => format!(
+                "Rollup<{}>",
+                value_type(result.type_, result.options.as_ref())
+            ),
+            None => "serde_json::Value".to_string(),
+        },
+        FieldType::Lookup | FieldType::MultipleLookupValues => {
+            match options.and_then(|o| o.result.as_deref()) {
+                Some(result) => {
+                    format!(
+                        "Lookup<{}>",
+                        value_type(result.type_, result.options.as_ref())
+                    )
+                }
+                None => "Vec<serde_json::Value>".to_string(),
+            }
+        }
+        FieldType::ExternalSyncSource | FieldType::Unknown => "serde_json::Value".to_string(),
+    }
+}
+
+/// Whether Airtable computes the field, so it cannot be written.
+pub fn is_computed(type_: FieldType) -> bool {
+    matches!(
+        type_,
+        FieldType::Formula
+            | FieldType::Rollup
+            | FieldType::Lookup
+            | FieldType::MultipleLookupValues
+            | FieldType::Count
+            | FieldType::AutoNumber
+            | FieldType::CreatedTime
+            | FieldType::LastModifiedTime
+            | FieldType::CreatedBy
+            | FieldType::LastModifiedBy
+            | FieldType::Button
+            | FieldType::ExternalSyncSource
+            | FieldType::AiText
+    )
+}
+
+/// Split a name into lowercase words on anything that is not alphanumeric and
+/// on lower to upper case changes.
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut prev_lower = false;
+
+    for c in name.chars() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            prev_lower = false;
+            continue;
+        }
+        if c.is_uppercase() && prev_lower && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        prev_lower = c.is_lowercase() || c.is_numeric();
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+/// The struct name for a table, e.g. `wish list` becomes `WishList`.
+pub fn type_name(name: &str) -> String {
+    let mut ident: String = words(name)
+        .iter()
+        .map(|w| {
+            let mut chars = w.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect();
+
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_numeric()) {
+        ident.insert_str(0, "Table");
+    }
+    if KEYWORDS.contains(&ident.as_str()) {
+        ident.push('_');
+    }
+
+    ident
+}
+
+/// The struct field name for a field, e.g. `Price (USD)` becomes `price_usd`.
+pub fn field_name(name: &str) -> String {
+    let mut ident = words(name).join("_");
+
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_numeric()) {
+        ident.insert_str(0, "field_");
+    }
+    if KEYWORDS.contains(&ident.as_str()) {
+        ident.push('_');
+    }
+
+    ident
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDate, Utc};
+    use schemars::JsonSchema;
+
+    use super::*;
+    use crate::airtable::{
+        AiText, Attachment, Barcode, Button, Checkbox, Computed, CreatedBy, Currency, Duration,
+        FieldResult, LastModifiedBy, LastModifiedTime, Lookup, MultipleSelects, Percent, Rating,
+        RecordLinks, RichText, Rollup, SchemaIssue, SchemaValidator, SingleSelect, User,
+    };
+
+    /// A record with a single field of type `T`, as codegen writes it.
+    #[derive(JsonSchema)]
+    #[allow(dead_code)]
+    struct Cell<T> {
+        value: Option<T>,
+    }
+
+    type Check = fn(&TableSchema) -> Vec<SchemaIssue>;
+
+    fn check<T: JsonSchema>(table: &TableSchema) -> Vec<SchemaIssue> {
+        SchemaValidator::new()
+            .register::<Cell<T>>(&table.name)
+            .check_tables(std::slice::from_ref(table))
+            .remove(0)
+            .issues
+    }
+
+    /// Every type codegen writes, keyed by how it writes it.
+    macro_rules! checks {
+        ($($t:ty),* $(,)?) => {
+            vec![$((stringify!($t).replace(' ', ""), check::<$t> as Check)),*]
+        };
+    }
+
+    fn options(precision: Option<u32>, result: Option<FieldType>) -> Option<FieldOptions> {
+        Some(FieldOptions {
+            precision,
+            result: result.map(|type_| {
+                Box::new(FieldResult {
+                    type_,
+                    options: None,
+                })
+            }),
+            ..Default::default()
+        })
+    }
+
+    /// Fails to build when a field type is added, until it is covered below.
+    fn covered(type_: FieldType) {
+        match type_ {
+            FieldType::SingleLineText
+            | FieldType::Email
+            | FieldType::Url
+            | FieldType::MultilineText
+            | FieldType::Number
+            | FieldType::Percent
+            | FieldType::Currency
+            | FieldType::SingleSelect
+            | FieldType::MultipleSelects
+            | FieldType::SingleCollaborator
+            | FieldType::MultipleCollaborators
+            | FieldType::MultipleRecordLinks
+            | FieldType::Date
+            | FieldType::DateTime
+            | FieldType::PhoneNumber
+            | FieldType::MultipleAttachments
+            | FieldType::Checkbox
+            | FieldType::Formula
+            | FieldType::CreatedTime
+            | FieldType::Rollup
+            | FieldType::Count
+            | FieldType::Lookup
+            | FieldType::MultipleLookupValues
+            | FieldType::AutoNumber
+            | FieldType::Barcode
+            | FieldType::Rating
+            | FieldType::RichText
+            | FieldType::Duration
+            | FieldType::LastModifiedTime
+            | FieldType::Button
+            | FieldType::CreatedBy
+            | FieldType::LastModifiedBy
+            | FieldType::ExternalSyncSource
+            | FieldType::AiText
+            | FieldType::Unknown => (),
+        }
+    }
+
+    #[test]
+    fn generated_types_pass_validation() {
+        let checks = checks![
+            String,
+            RichText,
+            SingleSelect,
+            i64,
+            f64,
+            Percent,
+            Currency,
+            Duration,
+            Rating,
+            Checkbox,
+            MultipleSelects,
+            RecordLinks,
+            NaiveDate,
+            DateTime<Utc>,
+            LastModifiedTime,
+            Vec<Attachment>,
+            User,
+            CreatedBy,
+            LastModifiedBy,
+            Vec<User>,
+            Barcode,
+            Button,
+            AiText,
+            Computed<f64>,
+            Computed<String>,
+            Rollup<f64>,
+            Lookup<String>,
+            Vec<serde_json::Value>,
+            serde_json::Value,
+        ];
+
+        let fields = [
+            (FieldType::SingleLineText, None),
+            (FieldType::Email, None),
+            (FieldType::Url, None),
+            (FieldType::MultilineText, None),
+            (FieldType::Number, options(Some(0), None)),
+            (FieldType::Number, options(Some(2), None)),
+            (FieldType::Percent, options(Some(1), None)),
+            (FieldType::Currency, options(Some(2), None)),
+            (FieldType::SingleSelect, None),
+            (FieldType::MultipleSelects, None),
+            (FieldType::SingleCollaborator, None),
+            (FieldType::MultipleCollaborators, None),
+            (FieldType::MultipleRecordLinks, None),
+            (FieldType::Date, None),
+            (FieldType::DateTime, None),
+            (FieldType::PhoneNumber, None),
+            (FieldType::MultipleAttachments, None),
+            (FieldType::Checkbox, None),
+            (FieldType::Formula, options(None, Some(FieldType::Number))),
+            (
+                FieldType::Formula,
+                options(None, Some(FieldType::SingleLineText)),
+            ),
+            (FieldType::Formula, None),
+            (FieldType::CreatedTime, None),
+            (FieldType::Rollup, options(None, Some(FieldType::Number))),
+            (FieldType::Rollup, None),
+            (FieldType::Count, None),
+            (
+                FieldType::Lookup,
+                options(None, Some(FieldType::SingleLineText)),
+            ),
+            (FieldType::MultipleLookupValues, None),
+            (FieldType::AutoNumber, None),
+            (FieldType::Barcode, None),
+            (FieldType::Rating, None),
+            (FieldType::RichText, None),
+            (FieldType::Duration, options(Some(0), None)),
+            (FieldType::Duration, options(Some(2), None)),
+            (FieldType::LastModifiedTime, None),
+            (FieldType::Button, None),
+            (FieldType::CreatedBy, None),
+            (FieldType::LastModifiedBy, None),
+            (FieldType::ExternalSyncSource, None),
+            (FieldType::AiText, None),
+            (FieldType::Unknown, None),
+        ];
+
+        for (type_, options) in fields {
+            covered(type_);
+            let field = FieldSchema {
+                id: "fldValue".to_string(),
+                name: "value".to_string(),
+                type_,
+                description: None,
+                options,
+            };
+            let table = TableSchema {
+                id: "tblCell".to_string(),
+                name: "cells".to_string(),
+                primary_field_id: field.id.to_string(),
+                description: None,
+                fields: vec![field],
+                views: Vec::new(),
+            };
+
+            let rust_type = rust_type(&table.fields[0]);
+            let Some((_, check)) = checks.iter().find(|(t, _)| *t == rust_type) else {
+                panic!("{type_:?} generates {rust_type}, which isn't checked");
+            };
+            assert_eq!(check(&table), vec![], "{type_:?} as {rust_type}");
+        }
+    }
+
+    #[test]
+    fn names_tables_and_fields() {
+        assert_eq!(type_name("wish list"), "WishList");
+        assert_eq!(type_name("2023 gifts"), "Table2023Gifts");
+        assert_eq!(type_name("Self"), "Self_");
+        assert_eq!(type_name("self"), "Self_");
+        assert_eq!(field_name("Price (USD)"), "price_usd");
+        assert_eq!(field_name("createdBy"), "created_by");
+        assert_eq!(field_name("type"), "type_");
+        assert_eq!(field_name("#"), "field_");
+    }
+
+    #[test]
+    fn dedupes_table_names() {
+        let table = |id: &str, name: &str| TableSchema {
+            id: id.to_string(),
+            name: name.to_string(),
+            primary_field_id: String::new(),
+            description: None,
+            fields: Vec::new(),
+            views: Vec::new(),
+        };
+
+        let code = generate(
+            "appTest",
+            &[
+                table("tbl1", "wish list"),
+                table("tbl2", "Wish-List"),
+                table("tbl3", "wish_list"),
+                table("tbl4", "Self"),
+            ],
+        );
+
+        assert!(code.contains("pub struct WishList {"));
+        assert!(code.contains("pub struct WishList2 {"));
+        assert!(code.contains("pub struct WishList3 {"));
+        assert!(code.contains("pub struct Self_ {"));
+    }
+
+    #[test]
+    fn dedupes_field_names() {
+        let field = |name: &str| FieldSchema {
+            id: String::new(),
+            name: name.to_string(),
+            type_: FieldType::SingleLineText,
+            description: None,
+            options: None,
+        };
+        let table = TableSchema {
+            id: "tbl1".to_string(),
+            name: "items".to_string(),
+            primary_field_id: String::new(),
+            description: None,
+            fields: vec![field("Name"), field("name"), field("NAME!")],
+            views: Vec::new(),
+        };
+
+        let code = generate_table(&table);
+
+        assert!(code.contains("pub name: Option<String>,"));
+        assert!(code.contains("pub name_2: Option<String>,"));
+        assert!(code.contains("pub name_3: Option<String>,"));
+    }
+}
@@ -0,0 +1,314 @@
+//! Comments on Airtable records.
+//!
+//! FROM: https://airtable.com/developers/web/api/list-comments
+use std::collections::HashMap;
+
+use chrono::{offset::Utc, DateTime};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::{error, Airtable, AirtableError, Deleted, Result, User};
+
+impl Airtable {
+    /// List the comments on a record, newest first.
+    /// FROM: https://airtable.com/developers/web/api/list-comments
+    pub async fn list_comments(&self, table: &str, record_id: &str) -> Result<Vec<Comment>> {
+        let mut comments = Vec::new();
+        let mut offset = String::new();
+
+        loop {
+            let mut query = vec![("pageSize", "100".to_string())];
+            if !offset.is_empty() {
+                query.push(("offset", offset));
+            }
+
+            // Build the request.
+            let request = self.request(
+                Method::GET,
+                format!("{table}/{record_id}/comments"),
+                (),
+                Some(query),
+            )?;
+
+            let resp = self.client.execute(request).await?;
+            match resp.status() {
+                StatusCode::OK => (),
+                _ => return Err(AirtableError::from_response(resp).await),
+            };
+
+            // Try to deserialize the response.
+            let r: CommentsResponse = error::json(resp).await?;
+
+            comments.extend(r.comments);
+            offset = r.offset.unwrap_or_default();
+
+            if offset.is_empty() {
+                return Ok(comments);
+            }
+        }
+    }
+
+    /// Comment on a record. Users are mentioned with [`Comment::mention`] in
+    /// the text. Pass `parent_comment_id` to reply to a comment.
+    /// FROM: https://airtable.com/developers/web/api/create-comment
+    pub async fn create_comment(
+        &self,
+        table: &str,
+        record_id: &str,
+        text: &str,
+        parent_comment_id: Option<&str>,
+    ) -> Result<Comment> {
+        // Build the request.
+        let request = self.request(
+            Method::POST,
+            format!("{table}/{record_id}/comments"),
+            NewComment {
+                text: text.to_string(),
+                parent_comment_id: parent_comment_id.map(|id| id.to_string()),
+            },
+            None,
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: Comment = error::json(resp).await?;
+
+        Ok(r)
+    }
+
+    /// Change the text of a comment. Only the author of a comment can do so.
+    /// FROM: https://airtable.com/developers/web/api/update-comment
+    pub async fn update_comment(
+        &self,
+        table: &str,
+        record_id: &str,
+        comment_id: &str,
+        text: &str,
+    ) -> Result<Comment> {
+        // Build the request.
+        let request = self.request(
+            Method::PATCH,
+            format!("{table}/{record_id}/comments/{comment_id}"),
+            NewComment {
+                text: text.to_string(),
+                parent_comment_id: None,
+            },
+            None,
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: Comment = error::json(resp).await?;
+
+        Ok(r)
+    }
+
+    /// Delete a comment.
+    /// FROM: https://airtable.com/developers/web/api/delete-comment
+    pub async fn delete_comment(
+        &self,
+        table: &str,
+        record_id: &str,
+        comment_id: &str,
+    ) -> Result<Deleted> {
+        // Build the request.
+        let request = self.request(
+            Method::DELETE,
+            format!("{table}/{record_id}/comments/{comment_id}"),
+            (),
+            None,
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: Deleted = error::json(resp).await?;
+
+        Ok(r)
+    }
+}
+
+/// A comment on a record.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: String,
+    pub author: User,
+    /// The text of the comment, mentions look like `@[usrXXXXXXXXXXXXXX]`.
+    pub text: String,
+    #[serde(rename = "createdTime")]
+    pub created_time: DateTime<Utc>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "lastUpdatedTime"
+    )]
+    pub last_updated_time: Option<DateTime<Utc>>,
+    /// The comment this is a reply to.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "parentCommentId"
+    )]
+    pub parent_comment_id: Option<String>,
+    /// The users and groups mentioned in the text, by id.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub mentioned: HashMap<String, Mention>,
+}
+
+impl Comment {
+    /// The text that mentions a user or group in a comment.
+    pub fn mention(id: &str) -> String {
+        format!("@[{id}]")
+    }
+
+    /// The text with the mentions replaced by the names of who was mentioned.
+    pub fn display_text(&self) -> String {
+        self.mentioned
+            .iter()
+            .fold(self.text.clone(), |text, (id, mention)| {
+                text.replace(&Self::mention(id), &format!("@{}", mention.display_name))
+            })
+    }
+}
+
+/// A user or group mentioned in a comment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mention {
+    pub id: String,
+    /// Either `user` or `userGroup`.
+    #[serde(rename = "type")]
+    pub type_: String,
+    #[serde(default, rename = "displayName")]
+    pub display_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct NewComment {
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "parentCommentId")]
+    parent_comment_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CommentsResponse {
+    comments: Vec<Comment>,
+    #[serde(default)]
+    offset: Option<String>,
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use std::{collections::HashMap, net::TcpListener};
+
+    use axum::{extract::Query, routing::get, Json, Router};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::airtable::AirtableBuilder;
+
+    fn comment(id: &str, created: &str, text: &str, mentioned: Value) -> Value {
+        json!({
+            "id": id,
+            "author": { "id": "usrAuthor", "email": "author@example.com" },
+            "text": text,
+            "createdTime": created,
+            "mentioned": mentioned,
+        })
+    }
+
+    fn display_text(text: &str, mentioned: Value) -> String {
+        let comment: Comment =
+            serde_json::from_value(comment("comA", "2023-10-01T12:00:00.000Z", text, mentioned))
+                .unwrap();
+        comment.display_text()
+    }
+
+    #[test]
+    fn replaces_mentions_with_names() {
+        let mentioned = json!({
+            "usrAlice": { "id": "usrAlice", "type": "user", "displayName": "Alice" },
+            "ugpHelpers": { "id": "ugpHelpers", "type": "userGroup", "displayName": "Helpers" },
+        });
+
+        assert_eq!(
+            display_text("@[usrAlice] can you ask @[ugpHelpers]?", mentioned.clone()),
+            "@Alice can you ask @Helpers?"
+        );
+        // Every mention of a user is replaced.
+        assert_eq!(
+            display_text("@[usrAlice], @[usrAlice]!", mentioned.clone()),
+            "@Alice, @Alice!"
+        );
+        // Text that only looks like a mention stays.
+        assert_eq!(
+            display_text("mail usrAlice or @[usrBob]", mentioned),
+            "mail usrAlice or @[usrBob]"
+        );
+        assert_eq!(display_text("no mentions", json!({})), "no mentions");
+    }
+
+    #[test]
+    fn mentions_a_user() {
+        assert_eq!(Comment::mention("usrAlice"), "@[usrAlice]");
+    }
+
+    #[tokio::test]
+    async fn lists_every_page_of_comments() {
+        // Two pages of comments, newest first.
+        let app = Router::new().route(
+            "/v0/appComments/items/recItem/comments",
+            get(|Query(query): Query<HashMap<String, String>>| async move {
+                assert_eq!(query["pageSize"], "100");
+                Json(match query.get("offset").map(String::as_str) {
+                    None => json!({
+                        "comments": [
+                            comment("comC", "2023-10-03T12:00:00.000Z", "third", json!({})),
+                            comment("comB", "2023-10-02T12:00:00.000Z", "second", json!({})),
+                        ],
+                        "offset": "page2",
+                    }),
+                    Some("page2") => json!({
+                        "comments": [
+                            comment("comA", "2023-10-01T12:00:00.000Z", "first", json!({})),
+                        ],
+                    }),
+                    Some(offset) => panic!("unexpected offset {offset}"),
+                })
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+
+        let airtable = AirtableBuilder::new("key", "appComments")
+            .endpoint(format!("http://{addr}/v0/"))
+            .build()
+            .unwrap();
+        let comments = airtable.list_comments("items", "recItem").await.unwrap();
+
+        let ids: Vec<_> = comments.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["comC", "comB", "comA"]);
+        assert!(comments
+            .windows(2)
+            .all(|c| c[0].created_time >= c[1].created_time));
+    }
+}
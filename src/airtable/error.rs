@@ -0,0 +1,457 @@
+//! Errors returned by the Airtable client.
+use std::{fmt, time::Duration};
+
+use chrono::{DateTime, Utc};
+use reqwest::{header, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_path_to_error::Segment;
+
+use super::{APICall, ErrorResponse, FieldWarning, Record};
+
+/// An error returned by the Airtable client.
+#[derive(Debug, thiserror::Error)]
+pub enum AirtableError {
+    /// The API key is missing, invalid or lacks permission (401 or 403).
+    #[error("airtable authentication failed ({status}): {error}")]
+    Unauthorized { status: StatusCode, error: ApiError },
+    /// The base, table or record does not exist (404).
+    #[error("airtable resource not found: {0}")]
+    NotFound(ApiError),
+    /// Too many requests were sent to the base (429).
+    #[error("airtable rate limit exceeded: {error}")]
+    RateLimited {
+        retry_after: Option<Duration>,
+        error: ApiError,
+    },
+    /// Airtable rejected the request (400 or 422).
+    #[error("invalid airtable request ({status}): {error}")]
+    InvalidRequest { status: StatusCode, error: ApiError },
+    /// Any other unsuccessful response.
+    #[error("unexpected airtable response ({status}): {error}")]
+    Api { status: StatusCode, error: ApiError },
+    /// The response could not be deserialized into the requested type.
+    #[error(
+        "failed to deserialize airtable response at `{path}` (record: {}, field: {}): {source}",
+        record_id.as_deref().unwrap_or("-"),
+        field.as_deref().unwrap_or("-")
+    )]
+    Deserialize {
+        record_id: Option<String>,
+        field: Option<String>,
+        path: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The request could not be sent or the response could not be read.
+    #[error("airtable transport error: {0}")]
+    Transport(#[from] reqwest_middleware::Error),
+    /// A file to upload could not be read.
+    #[error("failed to read the file to upload: {0}")]
+    Io(#[from] std::io::Error),
+    /// The client is misconfigured, e.g. it has an unusable API key or endpoint.
+    #[error("invalid airtable client configuration: {0}")]
+    Config(String),
+    /// The call requires an enterprise account id.
+    #[error("an enterprise account id is required")]
+    MissingEnterpriseAccount,
+    /// Some users could not be deleted.
+    #[error("failed to delete users: {errors:?}")]
+    DeleteUsers { errors: Vec<ErrorResponse> },
+}
+
+impl From<reqwest::Error> for AirtableError {
+    fn from(e: reqwest::Error) -> Self {
+        AirtableError::Transport(reqwest_middleware::Error::Reqwest(e))
+    }
+}
+
+impl AirtableError {
+    /// Build the error for an unsuccessful response.
+    pub(crate) async fn from_response(resp: Response) -> Self {
+        let status = resp.status();
+        let retry_after = resp
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| parse_retry_after(v, Utc::now()));
+
+        let body = match resp.text().await {
+            Ok(body) => body,
+            Err(e) => return e.into(),
+        };
+        let error = ApiError::parse(status, &body);
+
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                AirtableError::Unauthorized { status, error }
+            }
+            StatusCode::NOT_FOUND => AirtableError::NotFound(error),
+            StatusCode::TOO_MANY_REQUESTS => AirtableError::RateLimited { retry_after, error },
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => {
+                AirtableError::InvalidRequest { status, error }
+            }
+            _ => AirtableError::Api { status, error },
+        }
+    }
+
+    /// Returns true if the error means the requested resource does not exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, AirtableError::NotFound(_))
+    }
+
+    /// Returns true if the error means a record does not exist, as opposed to
+    /// a missing base, table or view, which means the client is misconfigured.
+    /// Airtable sends `NOT_FOUND` for a missing base too, so that can't be told
+    /// apart.
+    pub fn is_missing_record(&self) -> bool {
+        match self {
+            AirtableError::NotFound(error) => {
+                matches!(error.type_.as_str(), "NOT_FOUND" | "MODEL_ID_NOT_FOUND")
+            }
+            _ => false,
+        }
+    }
+
+    /// The error type reported by Airtable, e.g. `INVALID_PERMISSIONS`.
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            AirtableError::Unauthorized { error, .. }
+            | AirtableError::NotFound(error)
+            | AirtableError::RateLimited { error, .. }
+            | AirtableError::InvalidRequest { error, .. }
+            | AirtableError::Api { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// The `error` object from an unsuccessful Airtable response.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiError {
+    #[serde(default, skip_serializing_if = "String::is_empty", rename = "type")]
+    pub type_: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub message: String,
+}
+
+impl ApiError {
+    /// Parse an error body. Airtable sends either `{"error": {"type": ..,
+    /// "message": ..}}` or just `{"error": "NOT_FOUND"}`; anything else is kept
+    /// as the message.
+    fn parse(status: StatusCode, body: &str) -> Self {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Error {
+            Object(ApiError),
+            Type(String),
+        }
+
+        #[derive(Deserialize)]
+        struct Body {
+            error: Error,
+        }
+
+        match serde_json::from_str::<Body>(body) {
+            Ok(Body {
+                error: Error::Object(error),
+            }) => error,
+            Ok(Body {
+                error: Error::Type(type_),
+            }) => ApiError {
+                type_,
+                message: Default::default(),
+            },
+            Err(_) => ApiError {
+                type_: status
+                    .canonical_reason()
+                    .unwrap_or_default()
+                    .to_uppercase()
+                    .replace(' ', "_"),
+                message: body.to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.type_.is_empty(), self.message.is_empty()) {
+            (false, false) => write!(f, "{}: {}", self.type_, self.message),
+            (false, true) => f.write_str(&self.type_),
+            (true, _) => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Parse a `Retry-After` header, either a number of seconds or an HTTP date.
+/// A date in the past means now.
+pub(crate) fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Deserialize a successful response body, pointing at the record and field
+/// that failed if it does not match `T`.
+pub(crate) async fn json<T: DeserializeOwned>(resp: Response) -> Result<T, AirtableError> {
+    let body = resp.bytes().await?;
+    from_slice(&body)
+}
+
+/// Deserialize a response body, pointing at the record and field that failed
+/// if it does not match `T`.
+pub(crate) fn from_slice<T: DeserializeOwned>(body: &[u8]) -> Result<T, AirtableError> {
+    let de = &mut serde_json::Deserializer::from_slice(body);
+    serde_path_to_error::deserialize(de).map_err(|e| {
+        let path = e.path().to_string();
+        let segments: Vec<&Segment> = e.path().iter().collect();
+        let value: serde_json::Value = serde_json::from_slice(body).unwrap_or_default();
+
+        // Either a single record, `fields.name`, or a page of records,
+        // `records[i].fields.name`.
+        let (record, rest) = match segments.as_slice() {
+            [Segment::Map { key }, Segment::Seq { index }, rest @ ..] if key == "records" => {
+                (&value["records"][*index], rest)
+            }
+            rest => (&value, rest),
+        };
+        let field = match rest {
+            [Segment::Map { key }, Segment::Map { key: field }, ..] if key == "fields" => {
+                Some(field.to_string())
+            }
+            _ => None,
+        };
+        let record_id = record["id"].as_str().map(|id| id.to_string());
+
+        AirtableError::Deserialize {
+            record_id,
+            field,
+            path,
+            source: e.into_inner(),
+        }
+    })
+}
+
+/// Deserialize a page of records, leaving out the fields that don't match `T`
+/// and skipping the records that still don't.
+pub(crate) async fn json_lenient<T: DeserializeOwned>(
+    resp: Response,
+) -> Result<APICall<T>, AirtableError> {
+    let body = resp.bytes().await?;
+    let page: APICall<serde_json::Value> = from_slice(&body)?;
+
+    Ok(APICall {
+        offset: page.offset,
+        records: page.records.into_iter().filter_map(lenient).collect(),
+        typecast: page.typecast,
+    })
+}
+
+/// Deserialize a record, dropping the field that failed until the rest fits.
+fn lenient<T: DeserializeOwned>(record: Record<serde_json::Value>) -> Option<Record<T>> {
+    let Record {
+        id,
+        mut fields,
+        created_time,
+        mut warnings,
+    } = record;
+
+    loop {
+        let e = match serde_path_to_error::deserialize(&fields) {
+            Ok(fields) => {
+                return Some(Record {
+                    id,
+                    fields,
+                    created_time,
+                    warnings,
+                })
+            }
+            Err(e) => e,
+        };
+
+        let field = match e.path().iter().next() {
+            Some(Segment::Map { key }) => fields
+                .as_object_mut()
+                .and_then(|f| f.remove(key))
+                .map(|_| key.to_string()),
+            _ => None,
+        };
+        let Some(field) = field else {
+            log::warn!("[airtable-api] Skipping record {id}: {e}");
+            return None;
+        };
+
+        let warning = FieldWarning {
+            field,
+            path: e.path().to_string(),
+            message: e.into_inner().to_string(),
+        };
+        log::warn!("[airtable-api] Record {id}: leaving out {warning}");
+        warnings.push(warning);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::airtable::{Attachment, Barcode};
+
+    #[test]
+    fn parses_retry_after_seconds() {
+        let now = Utc::now();
+        assert_eq!(parse_retry_after("30", now), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn parses_retry_after_dates() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn parses_error_bodies() {
+        let error = ApiError::parse(StatusCode::NOT_FOUND, r#"{"error":"NOT_FOUND"}"#);
+        assert_eq!(error.type_, "NOT_FOUND");
+
+        let error = ApiError::parse(
+            StatusCode::NOT_FOUND,
+            r#"{"error":{"type":"TABLE_NOT_FOUND","message":"Could not find table"}}"#,
+        );
+        assert_eq!(error.type_, "TABLE_NOT_FOUND");
+        assert_eq!(error.message, "Could not find table");
+
+        let error = ApiError::parse(StatusCode::BAD_GATEWAY, "upstream down");
+        assert_eq!(error.type_, "BAD_GATEWAY");
+        assert_eq!(error.message, "upstream down");
+    }
+
+    #[test]
+    fn tells_missing_records_from_config_errors() {
+        let not_found = |type_: &str| {
+            AirtableError::NotFound(ApiError {
+                type_: type_.to_string(),
+                message: Default::default(),
+            })
+        };
+        assert!(not_found("NOT_FOUND").is_missing_record());
+        assert!(not_found("MODEL_ID_NOT_FOUND").is_missing_record());
+        assert!(!not_found("TABLE_NOT_FOUND").is_missing_record());
+        assert!(!not_found("VIEW_NAME_NOT_FOUND").is_missing_record());
+        assert!(!AirtableError::MissingEnterpriseAccount.is_missing_record());
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Item {
+        #[serde(deserialize_with = "crate::airtable::deserialize_null_string::deserialize")]
+        name: String,
+        #[serde(default)]
+        image: Vec<Attachment>,
+        #[serde(default)]
+        barcode: Option<Barcode>,
+    }
+
+    const PAGE: &str = r#"{"records": [
+        {"id": "recGood", "fields": {"name": "racket", "image": [{"id": "att1", "size": 10}]}},
+        {"id": "recBad", "fields": {
+            "name": "mat",
+            "image": [{"id": "att2", "size": 10}, {"id": "att3", "size": "big"}],
+            "barcode": {"text": 5, "type": "upce"}
+        }}
+    ]}"#;
+
+    fn record(fields: serde_json::Value) -> Record<serde_json::Value> {
+        Record {
+            id: "recLenient".to_string(),
+            fields,
+            created_time: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn strict_errors_point_at_the_value() {
+        let e = from_slice::<APICall<Item>>(PAGE.as_bytes()).unwrap_err();
+        let AirtableError::Deserialize {
+            record_id,
+            field,
+            path,
+            ..
+        } = e
+        else {
+            panic!("expected a deserialize error, got {e:?}");
+        };
+        assert_eq!(record_id.as_deref(), Some("recBad"));
+        assert_eq!(field.as_deref(), Some("image"));
+        assert_eq!(path, "records[1].fields.image[1].size");
+    }
+
+    #[test]
+    fn lenient_records_leave_out_malformed_fields() {
+        let page: APICall<serde_json::Value> = from_slice(PAGE.as_bytes()).unwrap();
+        let records: Vec<Record<Item>> = page.records.into_iter().filter_map(lenient).collect();
+
+        assert!(records[0].warnings.is_empty());
+        assert_eq!(records[0].fields.image.len(), 1);
+
+        let bad = &records[1];
+        assert_eq!(bad.fields.name, "mat");
+        assert!(bad.fields.image.is_empty());
+        assert!(bad.fields.barcode.is_none());
+        let paths: Vec<_> = bad
+            .warnings
+            .iter()
+            .map(|w| (w.field.as_str(), w.path.as_str()))
+            .collect();
+        assert_eq!(
+            paths,
+            [("barcode", "barcode.text"), ("image", "image[1].size")]
+        );
+    }
+
+    #[test]
+    fn lenient_strings_accept_null_only() {
+        let item: Record<Item> = lenient(record(serde_json::json!({"name": null}))).unwrap();
+        assert_eq!(item.fields.name, "");
+        assert!(item.warnings.is_empty());
+
+        // `name` is required, so a record whose only field is malformed is
+        // skipped once it is left out.
+        assert!(lenient::<Item>(record(serde_json::json!({"name": 5}))).is_none());
+
+        #[derive(Debug, Deserialize)]
+        struct Optional {
+            #[serde(
+                default,
+                deserialize_with = "crate::airtable::deserialize_null_string::deserialize"
+            )]
+            name: String,
+            price: f64,
+        }
+        let item: Record<Optional> =
+            lenient(record(serde_json::json!({"name": 5, "price": 2.5}))).unwrap();
+        assert_eq!(item.fields.name, "");
+        assert_eq!(item.fields.price, 2.5);
+        assert_eq!(item.warnings.len(), 1);
+        assert_eq!(item.warnings[0].path, "name");
+        assert!(item.warnings[0].message.contains("expected a string"));
+    }
+}
@@ -0,0 +1,1676 @@
+//! An in-memory stand-in for the Airtable API, so the client, the site and the
+//! end to end tests can run offline against fixture data.
+//!
+//! It lists records (with offset pagination, views, sorting and the formulas
+//! [`Formula`] builds), gets, creates, updates, upserts and deletes them, and
+//! lists, creates and changes the tables and fields of the base. Like Airtable it writes at most 10 records per
+//! request and answers errors as `{"error": {"type": ..., "message": ...}}`.
+//!
+//! ```ignore
+//! let fake = FakeAirtable::load("appFakeUnregistry", "end2end/fixtures/airtable.json")?;
+//! let addr = fake.spawn("127.0.0.1:0".parse()?)?;
+//! let airtable = fake.client(addr)?;
+//! ```
+use std::{
+    cmp::Ordering,
+    fs, io,
+    net::{SocketAddr, TcpListener},
+    path::Path as FilePath,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, patch, post},
+    Json, Router,
+};
+use chrono::{offset::Utc, DateTime, NaiveDate};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::{
+    Airtable, AirtableBuilder, Choice, FieldOptions, FieldSchema, FieldType, FieldUpdate, Formula,
+    NewField, NewTable, ParseFormulaError, Result, TableSchema, ViewSchema,
+};
+
+/// The most records Airtable creates, updates or deletes in one request.
+const MAX_RECORDS_PER_REQUEST: usize = 10;
+/// The largest page Airtable lists.
+const MAX_PAGE_SIZE: usize = 100;
+
+/// The tables and records to start the fake with.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Fixture {
+    #[serde(default)]
+    pub tables: Vec<TableFixture>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TableFixture {
+    pub name: String,
+    /// Generated when empty.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// The views of the table, a `Grid view` showing every record when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub views: Vec<ViewFixture>,
+    /// The fields created through the metadata API. The schema of any other
+    /// field is guessed from its values.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldSchema>,
+    #[serde(default)]
+    pub records: Vec<FakeRecord>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ViewFixture {
+    pub name: String,
+    /// Only the records matching this formula are in the view.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+}
+
+/// A record as the API returns it.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct FakeRecord {
+    /// Generated when empty.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "createdTime"
+    )]
+    pub created_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub fields: Map<String, Value>,
+}
+
+/// The fake Airtable, clones share the same records.
+#[derive(Debug, Clone)]
+pub struct FakeAirtable {
+    state: Arc<Mutex<FakeBase>>,
+}
+
+#[derive(Debug)]
+struct FakeBase {
+    id: String,
+    tables: Vec<FakeTable>,
+    next_id: u64,
+}
+
+#[derive(Debug)]
+struct FakeTable {
+    id: String,
+    name: String,
+    description: Option<String>,
+    views: Vec<FakeView>,
+    /// The fields created through the metadata API, the first is primary.
+    fields: Vec<FieldSchema>,
+    records: Vec<FakeRecord>,
+}
+
+#[derive(Debug)]
+struct FakeView {
+    id: String,
+    name: String,
+    filter: Option<Formula>,
+}
+
+impl FakeAirtable {
+    /// An empty base.
+    pub fn new<S: ToString>(base_id: S) -> Self {
+        FakeAirtable {
+            state: Arc::new(Mutex::new(FakeBase {
+                id: base_id.to_string(),
+                tables: Vec::new(),
+                next_id: 1,
+            })),
+        }
+    }
+
+    /// A base with the tables and records of `fixture`.
+    pub fn from_fixture<S: ToString>(
+        base_id: S,
+        fixture: Fixture,
+    ) -> std::result::Result<Self, ParseFormulaError> {
+        let fake = Self::new(base_id);
+        {
+            let mut base = fake.base();
+            for table in fixture.tables {
+                let mut views = Vec::new();
+                for view in table.views {
+                    let filter = view.filter.as_deref().map(str::parse).transpose()?;
+                    views.push(FakeView {
+                        id: base.generate_id("viw"),
+                        name: view.name,
+                        filter,
+                    });
+                }
+                if views.is_empty() {
+                    views.push(base.grid_view());
+                }
+
+                let id = match table.id.is_empty() {
+                    true => base.generate_id("tbl"),
+                    false => table.id,
+                };
+                let records = table
+                    .records
+                    .into_iter()
+                    .map(|r| base.store(r.id, r.created_time, r.fields))
+                    .collect();
+
+                base.tables.push(FakeTable {
+                    id,
+                    name: table.name,
+                    description: None,
+                    views,
+                    fields: table.fields,
+                    records,
+                });
+            }
+        }
+
+        Ok(fake)
+    }
+
+    /// A base with the tables and records of the JSON [`Fixture`] at `path`.
+    pub fn load<S: ToString>(base_id: S, path: impl AsRef<FilePath>) -> io::Result<Self> {
+        let fixture: Fixture = serde_json::from_slice(&fs::read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        Self::from_fixture(base_id, fixture)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// The current tables and records, e.g. to check what a test wrote.
+    pub fn snapshot(&self) -> Fixture {
+        let base = self.base();
+        Fixture {
+            tables: base
+                .tables
+                .iter()
+                .map(|t| TableFixture {
+                    name: t.name.to_string(),
+                    id: t.id.to_string(),
+                    views: t
+                        .views
+                        .iter()
+                        .map(|v| ViewFixture {
+                            name: v.name.to_string(),
+                            filter: v.filter.as_ref().map(|f| f.to_string()),
+                        })
+                        .collect(),
+                    fields: t.fields.clone(),
+                    records: t.records.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// The routes of the API, under `/v0`.
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route(
+                "/v0/meta/bases/:base/tables",
+                get(list_tables).post(create_table),
+            )
+            .route(
+                "/v0/meta/bases/:base/tables/:table/fields",
+                post(create_field),
+            )
+            .route(
+                "/v0/meta/bases/:base/tables/:table/fields/:field",
+                patch(update_field),
+            )
+            .route(
+                "/v0/:base/:table",
+                get(list_records)
+                    .post(create_records)
+                    .patch(update_records)
+                    .put(replace_records)
+                    .delete(delete_records),
+            )
+            .route(
+                "/v0/:base/:table/:record",
+                get(get_record)
+                    .patch(update_record)
+                    .put(replace_record)
+                    .delete(delete_record),
+            )
+            .with_state(self.clone())
+    }
+
+    /// Serve the API on `addr` in the background, port 0 picks a free port.
+    /// Returns the address it is served on. Must be called from within a
+    /// tokio runtime.
+    pub fn spawn(&self, addr: SocketAddr) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let server = axum::Server::from_tcp(listener)
+            .map_err(io::Error::other)?
+            .serve(self.router().into_make_service());
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                log::error!("[airtable-fake] Server failed: {e}");
+            }
+        });
+
+        Ok(addr)
+    }
+
+    /// The endpoint of the API served on `addr`, for AIRTABLE_ENDPOINT.
+    pub fn endpoint(addr: SocketAddr) -> String {
+        format!("http://{addr}/v0/")
+    }
+
+    /// A client for the API served on `addr`.
+    pub fn client(&self, addr: SocketAddr) -> Result<Airtable> {
+        AirtableBuilder::new("fake", &self.base().id)
+            .endpoint(Self::endpoint(addr))
+            .build()
+    }
+
+    fn base(&self) -> MutexGuard<'_, FakeBase> {
+        // A handler panicking leaves the records as they were, keep going.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Check the request is for this base and has an API key.
+    fn authorize(
+        &self,
+        headers: &HeaderMap,
+        base_id: &str,
+    ) -> std::result::Result<MutexGuard<'_, FakeBase>, FakeError> {
+        let authorized = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("Bearer ") && v.len() > "Bearer ".len());
+        if !authorized {
+            return Err(error(
+                StatusCode::UNAUTHORIZED,
+                "AUTHENTICATION_REQUIRED",
+                "Authentication required",
+            ));
+        }
+
+        let base = self.base();
+        if base.id != base_id {
+            return Err(not_found());
+        }
+
+        Ok(base)
+    }
+}
+
+/// An error answered the way Airtable answers it.
+#[derive(Debug)]
+struct FakeError {
+    status: StatusCode,
+    body: Value,
+}
+
+impl IntoResponse for FakeError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body)).into_response()
+    }
+}
+
+type Reply = std::result::Result<Value, FakeError>;
+
+fn reply(reply: Reply) -> Response {
+    match reply {
+        Ok(value) => Json(value).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+fn error(status: StatusCode, type_: &str, message: impl ToString) -> FakeError {
+    FakeError {
+        status,
+        body: json!({ "error": { "type": type_, "message": message.to_string() } }),
+    }
+}
+
+fn not_found() -> FakeError {
+    FakeError {
+        status: StatusCode::NOT_FOUND,
+        body: json!({ "error": "NOT_FOUND" }),
+    }
+}
+
+fn invalid(message: impl ToString) -> FakeError {
+    error(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "INVALID_REQUEST_UNKNOWN",
+        message,
+    )
+}
+
+fn parse_body<T: serde::de::DeserializeOwned>(body: &Bytes) -> std::result::Result<T, FakeError> {
+    serde_json::from_slice(body).map_err(|e| invalid(format!("Invalid request: {e}")))
+}
+
+async fn list_tables(
+    State(fake): State<FakeAirtable>,
+    Path(base_id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    match fake.authorize(&headers, &base_id) {
+        Ok(base) => Json(json!({ "tables": base.schema() })).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn create_table(
+    State(fake): State<FakeAirtable>,
+    Path(base_id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    match fake.authorize(&headers, &base_id) {
+        Ok(mut base) => reply(parse_body(&body).and_then(|table| base.create_table(table))),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn create_field(
+    State(fake): State<FakeAirtable>,
+    Path((base_id, table)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    match fake.authorize(&headers, &base_id) {
+        Ok(mut base) => reply(parse_body(&body).and_then(|field| base.create_field(&table, field))),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn update_field(
+    State(fake): State<FakeAirtable>,
+    Path((base_id, table, field_id)): Path<(String, String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    match fake.authorize(&headers, &base_id) {
+        Ok(mut base) => {
+            reply(parse_body(&body).and_then(|update| base.update_field(&table, &field_id, update)))
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn list_records(
+    State(fake): State<FakeAirtable>,
+    Path((base_id, table)): Path<(String, String)>,
+    Query(query): Query<Vec<(String, String)>>,
+    headers: HeaderMap,
+) -> Response {
+    match fake.authorize(&headers, &base_id) {
+        Ok(base) => reply(base.list(&table, &query)),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn get_record(
+    State(fake): State<FakeAirtable>,
+    Path((base_id, table, record_id)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    match fake.authorize(&headers, &base_id) {
+        Ok(base) => reply(base.get(&table, &record_id)),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn create_records(
+    State(fake): State<FakeAirtable>,
+    Path((base_id, table)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    match fake.authorize(&headers, &base_id) {
+        Ok(mut base) => reply(parse_body(&body).and_then(|call| base.create(&table, call))),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn update_records(
+    State(fake): State<FakeAirtable>,
+    Path((base_id, table)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    match fake.authorize(&headers, &base_id) {
+        Ok(mut base) => reply(parse_body(&body).and_then(|call| base.update(&table, call, false))),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn replace_records(
+    State(fake): State<FakeAirtable>,
+    Path((base_id, table)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    match fake.authorize(&headers, &base_id) {
+        Ok(mut base) => reply(parse_body(&body).and_then(|call| base.update(&table, call, true))),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn update_record(
+    State(fake): State<FakeAirtable>,
+    Path((base_id, table, record_id)): Path<(String, String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    match fake.authorize(&headers, &base_id) {
+        Ok(mut base) => reply(
+            parse_body(&body).and_then(|record| base.update_one(&table, &record_id, record, false)),
+        ),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn replace_record(
+    State(fake): State<FakeAirtable>,
+    Path((base_id, table, record_id)): Path<(String, String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    match fake.authorize(&headers, &base_id) {
+        Ok(mut base) => reply(
+            parse_body(&body).and_then(|record| base.update_one(&table, &record_id, record, true)),
+        ),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn delete_records(
+    State(fake): State<FakeAirtable>,
+    Path((base_id, table)): Path<(String, String)>,
+    Query(query): Query<Vec<(String, String)>>,
+    headers: HeaderMap,
+) -> Response {
+    let ids: Vec<String> = query
+        .into_iter()
+        .filter(|(k, _)| k == "records[]")
+        .map(|(_, v)| v)
+        .collect();
+
+    match fake.authorize(&headers, &base_id) {
+        Ok(mut base) => reply(base.delete(&table, &ids)),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn delete_record(
+    State(fake): State<FakeAirtable>,
+    Path((base_id, table, record_id)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    match fake.authorize(&headers, &base_id) {
+        Ok(mut base) => reply(
+            base.delete(&table, &[record_id])
+                .map(|r| r["records"][0].clone()),
+        ),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// The body of a create, update or upsert.
+#[derive(Debug, Deserialize)]
+struct WriteCall {
+    #[serde(default)]
+    records: Option<Vec<WriteRecord>>,
+    /// Creating a single record sends its fields instead of `records`.
+    #[serde(default)]
+    fields: Option<Map<String, Value>>,
+    #[serde(default, rename = "performUpsert")]
+    perform_upsert: Option<PerformUpsert>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WriteRecord {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    fields: Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PerformUpsert {
+    #[serde(rename = "fieldsToMergeOn")]
+    fields_to_merge_on: Vec<String>,
+}
+
+impl FakeBase {
+    fn generate_id(&mut self, prefix: &str) -> String {
+        let id = format!("{prefix}{:014}", self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// A record ready to store, Airtable doesn't keep empty cells.
+    fn store(
+        &mut self,
+        id: String,
+        created_time: Option<DateTime<Utc>>,
+        mut fields: Map<String, Value>,
+    ) -> FakeRecord {
+        fields.retain(|_, v| !v.is_null());
+        FakeRecord {
+            id: match id.is_empty() {
+                true => self.generate_id("rec"),
+                false => id,
+            },
+            created_time: created_time.or_else(|| Some(Utc::now())),
+            fields,
+        }
+    }
+
+    fn table_index(&self, table: &str) -> std::result::Result<usize, FakeError> {
+        self.tables
+            .iter()
+            .position(|t| t.name == table || t.id == table)
+            .ok_or_else(|| {
+                error(
+                    StatusCode::NOT_FOUND,
+                    "TABLE_NOT_FOUND",
+                    format!("Could not find table {table} in application {}", self.id),
+                )
+            })
+    }
+
+    fn table(&self, table: &str) -> std::result::Result<&FakeTable, FakeError> {
+        Ok(&self.tables[self.table_index(table)?])
+    }
+
+    fn schema(&self) -> Vec<TableSchema> {
+        self.tables.iter().map(FakeTable::schema).collect()
+    }
+
+    fn grid_view(&mut self) -> FakeView {
+        FakeView {
+            id: self.generate_id("viw"),
+            name: "Grid view".to_string(),
+            filter: None,
+        }
+    }
+
+    fn create_table(&mut self, table: NewTable) -> Reply {
+        if table.name.is_empty() || self.tables.iter().any(|t| t.name == table.name) {
+            return Err(error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "DUPLICATE_OR_EMPTY_TABLE_NAME",
+                format!("Table name {:?} is empty or already used", table.name),
+            ));
+        }
+        if table.fields.is_empty() {
+            return Err(invalid("A table needs at least one field"));
+        }
+
+        let mut fields: Vec<FieldSchema> = Vec::new();
+        for field in table.fields {
+            if fields.iter().any(|f| f.name == field.name) {
+                return Err(duplicate_field(&field.name));
+            }
+            fields.push(self.new_field(field)?);
+        }
+
+        let table = FakeTable {
+            id: self.generate_id("tbl"),
+            name: table.name,
+            description: table.description,
+            views: vec![self.grid_view()],
+            fields,
+            records: Vec::new(),
+        };
+        let schema = table.schema();
+        self.tables.push(table);
+
+        Ok(json!(schema))
+    }
+
+    fn create_field(&mut self, table: &str, field: NewField) -> Reply {
+        let t = self.table_index(table)?;
+        if self.tables[t].schema().field(&field.name).is_some() {
+            return Err(duplicate_field(&field.name));
+        }
+
+        let field = self.new_field(field)?;
+        self.tables[t].fields.push(field.clone());
+
+        Ok(json!(field))
+    }
+
+    /// A field to add to a table. Like Airtable, links must give the id of
+    /// an existing table.
+    fn new_field(&mut self, field: NewField) -> std::result::Result<FieldSchema, FakeError> {
+        if field.type_ == FieldType::MultipleRecordLinks {
+            let linked = field
+                .options
+                .as_ref()
+                .and_then(|o| o.linked_table_id.as_ref());
+            if !linked.is_some_and(|id| self.tables.iter().any(|t| &t.id == id)) {
+                return Err(invalid(format!(
+                    "Field {} links to an unknown table {linked:?}",
+                    field.name
+                )));
+            }
+        }
+
+        Ok(FieldSchema {
+            id: self.generate_id("fld"),
+            name: field.name,
+            type_: field.type_,
+            description: field.description,
+            options: field.options,
+        })
+    }
+
+    fn update_field(&mut self, table: &str, field_id: &str, update: FieldUpdate) -> Reply {
+        let t = self.table_index(table)?;
+        let schema = self.tables[t].schema();
+        let field = schema.field(field_id).ok_or_else(not_found)?;
+        let table = &mut self.tables[t];
+
+        // A guessed field is kept once it is changed.
+        let i = match table.fields.iter().position(|f| f.id == field.id) {
+            Some(i) => i,
+            None => {
+                table.fields.push(field.clone());
+                table.fields.len() - 1
+            }
+        };
+
+        if let Some(name) = update.name {
+            if name != field.name && schema.field(&name).is_some() {
+                return Err(duplicate_field(&name));
+            }
+            for record in &mut table.records {
+                if let Some(value) = record.fields.remove(&field.name) {
+                    record.fields.insert(name.to_string(), value);
+                }
+            }
+            table.fields[i].name = name;
+        }
+        if let Some(description) = update.description {
+            table.fields[i].description = Some(description);
+        }
+
+        Ok(json!(table.fields[i]))
+    }
+
+    fn list(&self, table: &str, query: &[(String, String)]) -> Reply {
+        let table = self.table(table)?;
+        let param = |key: &str| {
+            query
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        };
+
+        let mut records: Vec<&FakeRecord> = table.records.iter().collect();
+
+        if let Some(view) = param("view") {
+            let view = table
+                .views
+                .iter()
+                .find(|v| v.name == view || v.id == view)
+                .ok_or_else(|| {
+                    error(
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "VIEW_NAME_NOT_FOUND",
+                        format!("Could not find view {view} in table {}", table.name),
+                    )
+                })?;
+            if let Some(filter) = &view.filter {
+                records = filter_records(records, filter)?;
+            }
+        }
+
+        if let Some(formula) = param("filterByFormula") {
+            let formula: Formula = formula.parse().map_err(|e| invalid_formula(&e))?;
+            records = filter_records(records, &formula)?;
+        }
+
+        let mut sorts = Vec::new();
+        while let Some(field) = param(&format!("sort[{}][field]", sorts.len())) {
+            let desc = param(&format!("sort[{}][direction]", sorts.len())) == Some("desc");
+            sorts.push((field, desc));
+        }
+        records.sort_by(|a, b| {
+            sorts
+                .iter()
+                .map(|(field, desc)| {
+                    let order = order(a.fields.get(*field), b.fields.get(*field));
+                    if *desc {
+                        order.reverse()
+                    } else {
+                        order
+                    }
+                })
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+
+        if let Some(max_records) = param("maxRecords") {
+            let max_records: usize = max_records
+                .parse()
+                .map_err(|_| invalid("maxRecords must be a number"))?;
+            records.truncate(max_records);
+        }
+
+        let page_size = match param("pageSize") {
+            None => MAX_PAGE_SIZE,
+            Some(page_size) => match page_size.parse() {
+                Ok(n) if n > 0 && n <= MAX_PAGE_SIZE => n,
+                _ => return Err(invalid(format!("pageSize must be 1 to {MAX_PAGE_SIZE}"))),
+            },
+        };
+        let start = match param("offset") {
+            None => 0,
+            Some(offset) => offset
+                .strip_prefix("itr")
+                .and_then(|o| o.parse().ok())
+                .filter(|o| *o <= records.len())
+                .ok_or_else(|| {
+                    error(
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "LIST_RECORDS_ITERATOR_NOT_AVAILABLE",
+                        "The offset is invalid or has expired",
+                    )
+                })?,
+        };
+        let end = records.len().min(start + page_size);
+
+        let fields: Vec<&str> = query
+            .iter()
+            .filter(|(k, _)| k == "fields[]")
+            .map(|(_, v)| v.as_str())
+            .collect();
+        let page: Vec<Value> = records[start..end]
+            .iter()
+            .map(|r| {
+                let mut r = (*r).clone();
+                if !fields.is_empty() {
+                    r.fields.retain(|k, _| fields.contains(&k.as_str()));
+                }
+                json!(r)
+            })
+            .collect();
+
+        let mut response = json!({ "records": page });
+        if end < records.len() {
+            response["offset"] = json!(format!("itr{end:014}"));
+        }
+
+        Ok(response)
+    }
+
+    fn get(&self, table: &str, record_id: &str) -> Reply {
+        let table = self.table(table)?;
+        let record = table.record(record_id).ok_or_else(not_found)?;
+
+        Ok(json!(table.records[record]))
+    }
+
+    fn create(&mut self, table: &str, call: WriteCall) -> Reply {
+        let t = self.table_index(table)?;
+
+        // A single record can be created by sending just its fields.
+        if let Some(fields) = call.fields {
+            let record = self.store(String::new(), None, fields);
+            self.tables[t].records.push(record.clone());
+            return Ok(json!(record));
+        }
+
+        let records = check_records(call.records)?;
+        let mut created = Vec::new();
+        for record in records {
+            let record = self.store(String::new(), None, record.fields);
+            self.tables[t].records.push(record.clone());
+            created.push(record);
+        }
+
+        Ok(json!({ "records": created }))
+    }
+
+    fn update(&mut self, table: &str, call: WriteCall, replace: bool) -> Reply {
+        let t = self.table_index(table)?;
+        let records = check_records(call.records)?;
+
+        // Work out which record each one updates before changing anything, so a
+        // failing request leaves the table as it was.
+        let mut targets = Vec::new();
+        for record in &records {
+            let target = match (&record.id, &call.perform_upsert) {
+                (Some(id), _) => Some(self.tables[t].record(id).ok_or_else(not_found)?),
+                (None, Some(upsert)) => self.tables[t].find_match(record, upsert)?,
+                (None, None) => {
+                    return Err(invalid("Each record needs an id, or use performUpsert"))
+                }
+            };
+            targets.push(target);
+        }
+
+        let mut updated = Vec::new();
+        let mut created_ids = Vec::new();
+        let mut updated_ids = Vec::new();
+        for (record, target) in records.into_iter().zip(targets) {
+            let record = match target {
+                Some(i) => {
+                    let existing = &mut self.tables[t].records[i];
+                    write_fields(existing, record.fields, replace);
+                    updated_ids.push(existing.id.to_string());
+                    existing.clone()
+                }
+                None => {
+                    let record = self.store(String::new(), None, record.fields);
+                    created_ids.push(record.id.to_string());
+                    self.tables[t].records.push(record.clone());
+                    record
+                }
+            };
+            updated.push(record);
+        }
+
+        let mut response = json!({ "records": updated });
+        if call.perform_upsert.is_some() {
+            response["createdRecords"] = json!(created_ids);
+            response["updatedRecords"] = json!(updated_ids);
+        }
+
+        Ok(response)
+    }
+
+    fn update_one(
+        &mut self,
+        table: &str,
+        record_id: &str,
+        record: WriteRecord,
+        replace: bool,
+    ) -> Reply {
+        let t = self.table_index(table)?;
+        let i = self.tables[t].record(record_id).ok_or_else(not_found)?;
+
+        let existing = &mut self.tables[t].records[i];
+        write_fields(existing, record.fields, replace);
+
+        Ok(json!(existing))
+    }
+
+    fn delete(&mut self, table: &str, ids: &[String]) -> Reply {
+        let t = self.table_index(table)?;
+        if ids.is_empty() {
+            return Err(invalid("Specify the records to delete with records[]"));
+        }
+        if ids.len() > MAX_RECORDS_PER_REQUEST {
+            return Err(too_many_records());
+        }
+        if ids.iter().any(|id| self.tables[t].record(id).is_none()) {
+            return Err(not_found());
+        }
+
+        self.tables[t].records.retain(|r| !ids.contains(&r.id));
+
+        let deleted: Vec<Value> = ids
+            .iter()
+            .map(|id| json!({ "id": id, "deleted": true }))
+            .collect();
+        Ok(json!({ "records": deleted }))
+    }
+}
+
+impl FakeTable {
+    fn record(&self, id: &str) -> Option<usize> {
+        self.records.iter().position(|r| r.id == id)
+    }
+
+    /// The record an upsert updates: the one whose merge fields all match.
+    fn find_match(
+        &self,
+        record: &WriteRecord,
+        upsert: &PerformUpsert,
+    ) -> std::result::Result<Option<usize>, FakeError> {
+        let matches: Vec<usize> = self
+            .records
+            .iter()
+            .enumerate()
+            .filter(|(_, existing)| {
+                upsert
+                    .fields_to_merge_on
+                    .iter()
+                    .all(|f| existing.fields.get(f) == record.fields.get(f))
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        match matches.len() {
+            0 => Ok(None),
+            1 => Ok(Some(matches[0])),
+            _ => Err(error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "INVALID_RECORDS",
+                "More than one record matches the fields to merge on",
+            )),
+        }
+    }
+
+    /// The schema: the created fields, then the others with their types
+    /// guessed from the values.
+    fn schema(&self) -> TableSchema {
+        let mut fields: Vec<FieldSchema> = self.fields.clone();
+        for record in &self.records {
+            for (name, value) in &record.fields {
+                let choices = match value {
+                    Value::Array(values) => values
+                        .iter()
+                        .filter_map(|v| v.as_str())
+                        .map(|v| v.to_string())
+                        .collect(),
+                    _ => Vec::new(),
+                };
+
+                match fields.iter_mut().find(|f| &f.name == name) {
+                    None => {
+                        let (type_, options) = guess_type(value);
+                        fields.push(FieldSchema {
+                            id: format!(
+                                "fld{}{:03}",
+                                self.id.trim_start_matches("tbl"),
+                                fields.len() - self.fields.len()
+                            ),
+                            name: name.to_string(),
+                            type_,
+                            description: None,
+                            options,
+                        });
+                    }
+                    // Collect every choice of a select field.
+                    Some(field) => {
+                        if let Some(options) = &mut field.options {
+                            for choice in choices {
+                                if !options.choices.iter().any(|c| c.name == choice) {
+                                    options.choices.push(Choice {
+                                        name: choice,
+                                        ..Default::default()
+                                    });
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        TableSchema {
+            id: self.id.to_string(),
+            name: self.name.to_string(),
+            // Record fields are kept in name order, so guess the primary field
+            // unless the table was created with one.
+            primary_field_id: self
+                .fields
+                .first()
+                .or_else(|| fields.iter().find(|f| f.name.eq_ignore_ascii_case("name")))
+                .or(fields.first())
+                .map(|f| f.id.to_string())
+                .unwrap_or_default(),
+            description: self.description.clone(),
+            fields,
+            views: self
+                .views
+                .iter()
+                .map(|v| ViewSchema {
+                    id: v.id.to_string(),
+                    name: v.name.to_string(),
+                    type_: "grid".to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn guess_type(value: &Value) -> (FieldType, Option<FieldOptions>) {
+    match value {
+        Value::Bool(_) => (FieldType::Checkbox, None),
+        Value::Number(n) => (
+            FieldType::Number,
+            Some(FieldOptions {
+                precision: Some(if n.is_f64() { 2 } else { 0 }),
+                ..Default::default()
+            }),
+        ),
+        Value::String(s) if s.contains('\n') => (FieldType::MultilineText, None),
+        Value::String(_) => (FieldType::SingleLineText, None),
+        Value::Array(values) if values.iter().any(|v| v.get("url").is_some()) => {
+            (FieldType::MultipleAttachments, None)
+        }
+        Value::Array(values) => (
+            FieldType::MultipleSelects,
+            Some(FieldOptions {
+                choices: values
+                    .iter()
+                    .filter_map(|v| v.as_str())
+                    .map(|v| Choice {
+                        name: v.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }),
+        ),
+        _ => (FieldType::Unknown, None),
+    }
+}
+
+fn check_records(
+    records: Option<Vec<WriteRecord>>,
+) -> std::result::Result<Vec<WriteRecord>, FakeError> {
+    match records {
+        None => Err(error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "INVALID_REQUEST_MISSING_FIELDS",
+            "Could not find field \"records\" in the request body",
+        )),
+        Some(records) if records.len() > MAX_RECORDS_PER_REQUEST => Err(too_many_records()),
+        Some(records) => Ok(records),
+    }
+}
+
+fn duplicate_field(name: &str) -> FakeError {
+    error(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "DUPLICATE_OR_EMPTY_FIELD_NAME",
+        format!("Field name {name:?} is already used"),
+    )
+}
+
+fn too_many_records() -> FakeError {
+    error(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "INVALID_RECORDS",
+        format!("You can only write up to {MAX_RECORDS_PER_REQUEST} records in one request"),
+    )
+}
+
+fn invalid_formula(e: &dyn std::fmt::Display) -> FakeError {
+    error(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "INVALID_FILTER_BY_FORMULA",
+        format!("The formula for filtering records is invalid: {e}"),
+    )
+}
+
+/// Set the fields of a record. Updating keeps the fields not given and clears
+/// those given as null, replacing clears every field not given.
+fn write_fields(record: &mut FakeRecord, fields: Map<String, Value>, replace: bool) {
+    if replace {
+        record.fields.clear();
+    }
+    for (name, value) in fields {
+        if value.is_null() {
+            record.fields.remove(&name);
+        } else {
+            record.fields.insert(name, value);
+        }
+    }
+}
+
+fn filter_records<'a>(
+    records: Vec<&'a FakeRecord>,
+    formula: &Formula,
+) -> std::result::Result<Vec<&'a FakeRecord>, FakeError> {
+    let mut matching = Vec::new();
+    for record in records {
+        let value = eval(formula, record).map_err(|e| invalid_formula(&e))?;
+        if truthy(&value) {
+            matching.push(record);
+        }
+    }
+    Ok(matching)
+}
+
+/// Evaluate a formula for a record, with Airtable's loose typing: blank equals
+/// an empty string, zero and false, and lists are joined into text.
+fn eval(formula: &Formula, record: &FakeRecord) -> std::result::Result<Value, String> {
+    Ok(match formula {
+        Formula::Field(name) => cell(record.fields.get(name)),
+        Formula::String(value) => Value::String(value.to_string()),
+        Formula::Number(n) => json!(n),
+        Formula::Bool(b) => Value::Bool(*b),
+        Formula::DateTime(date) => Value::String(date.to_rfc3339()),
+        Formula::Compare(lhs, op, rhs) => {
+            let (lhs, rhs) = (eval(lhs, record)?, eval(rhs, record)?);
+            let order = if is_numeric(&lhs) || is_numeric(&rhs) {
+                number(&lhs).partial_cmp(&number(&rhs))
+            } else {
+                Some(text(&lhs).cmp(&text(&rhs)))
+            };
+
+            use super::Comparison::*;
+            Value::Bool(match (op, order) {
+                (NotEqual, None) => true,
+                (_, None) => false,
+                (Equal, Some(o)) => o.is_eq(),
+                (NotEqual, Some(o)) => o.is_ne(),
+                (GreaterThan, Some(o)) => o.is_gt(),
+                (GreaterThanOrEqual, Some(o)) => o.is_ge(),
+                (LessThan, Some(o)) => o.is_lt(),
+                (LessThanOrEqual, Some(o)) => o.is_le(),
+            })
+        }
+        Formula::And(formulas) => {
+            for formula in formulas {
+                if !truthy(&eval(formula, record)?) {
+                    return Ok(Value::Bool(false));
+                }
+            }
+            Value::Bool(true)
+        }
+        Formula::Or(formulas) => {
+            for formula in formulas {
+                if truthy(&eval(formula, record)?) {
+                    return Ok(Value::Bool(true));
+                }
+            }
+            Value::Bool(false)
+        }
+        Formula::Not(formula) => Value::Bool(!truthy(&eval(formula, record)?)),
+        Formula::Function(name, args) => {
+            let args = args
+                .iter()
+                .map(|a| eval(a, record))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            function(name, &args, record)?
+        }
+        Formula::Raw(raw) => {
+            let formula: Formula = raw.parse().map_err(|e: ParseFormulaError| e.to_string())?;
+            eval(&formula, record)?
+        }
+    })
+}
+
+fn function(name: &str, args: &[Value], record: &FakeRecord) -> std::result::Result<Value, String> {
+    let arg = |i: usize| args.get(i).cloned().unwrap_or(Value::Null);
+
+    Ok(match name.to_uppercase().as_str() {
+        "RECORD_ID" => Value::String(record.id.to_string()),
+        "BLANK" => Value::Null,
+        "TRUE" => Value::Bool(true),
+        "FALSE" => Value::Bool(false),
+        "AND" => Value::Bool(args.iter().all(truthy)),
+        "OR" => Value::Bool(args.iter().any(truthy)),
+        "NOT" => Value::Bool(!truthy(&arg(0))),
+        "FIND" | "SEARCH" => {
+            let (needle, haystack) = (text(&arg(0)), text(&arg(1)));
+            let (needle, haystack) = match name.eq_ignore_ascii_case("SEARCH") {
+                true => (needle.to_lowercase(), haystack.to_lowercase()),
+                false => (needle, haystack),
+            };
+            match haystack.find(&needle) {
+                Some(i) => json!(haystack[..i].chars().count() + 1),
+                None => json!(0),
+            }
+        }
+        "CONCATENATE" => Value::String(args.iter().map(text).collect()),
+        "LOWER" => Value::String(text(&arg(0)).to_lowercase()),
+        "UPPER" => Value::String(text(&arg(0)).to_uppercase()),
+        "TRIM" => Value::String(text(&arg(0)).trim().to_string()),
+        "LEN" => json!(text(&arg(0)).chars().count()),
+        "IS_AFTER" | "IS_BEFORE" | "IS_SAME" => {
+            let (Some(a), Some(b)) = (date(&arg(0)), date(&arg(1))) else {
+                return Ok(Value::Bool(false));
+            };
+            Value::Bool(match name.to_uppercase().as_str() {
+                "IS_AFTER" => a > b,
+                "IS_BEFORE" => a < b,
+                _ => {
+                    let format = match text(&arg(2)).as_str() {
+                        "year" | "years" => "%Y",
+                        "month" | "months" => "%Y-%m",
+                        "day" | "days" => "%Y-%m-%d",
+                        "hour" | "hours" => "%Y-%m-%dT%H",
+                        "minute" | "minutes" => "%Y-%m-%dT%H:%M",
+                        _ => "%Y-%m-%dT%H:%M:%S%.f",
+                    };
+                    a.format(format).to_string() == b.format(format).to_string()
+                }
+            })
+        }
+        _ => return Err(format!("Unknown function names: {name}")),
+    })
+}
+
+/// The value of a cell in a formula.
+fn cell(value: Option<&Value>) -> Value {
+    match value {
+        None => Value::Null,
+        Some(Value::Array(values)) => Value::String(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Object(o) => ["name", "filename", "url"]
+                        .iter()
+                        .find_map(|k| o.get(*k))
+                        .map(text)
+                        .unwrap_or_default(),
+                    v => text(v),
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        Some(value) => value.clone(),
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(values) => !values.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+fn is_numeric(value: &Value) -> bool {
+    matches!(value, Value::Number(_) | Value::Bool(_))
+}
+
+fn number(value: &Value) -> f64 {
+    match value {
+        Value::Null => 0.0,
+        Value::Bool(b) => *b as u8 as f64,
+        Value::Number(n) => n.as_f64().unwrap_or(f64::NAN),
+        Value::String(s) if s.is_empty() => 0.0,
+        Value::String(s) => s.trim().parse().unwrap_or(f64::NAN),
+        _ => f64::NAN,
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.to_string(),
+        Value::Bool(true) => "1".to_string(),
+        Value::Bool(false) => "0".to_string(),
+        value => value.to_string(),
+    }
+}
+
+fn date(value: &Value) -> Option<DateTime<Utc>> {
+    let value = text(value);
+    DateTime::parse_from_rfc3339(&value)
+        .map(|d| d.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|d| d.and_utc())
+        })
+}
+
+/// The order of two cells when sorting, blank cells first.
+fn order(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    let (a, b) = (cell(a), cell(b));
+    match (&a, &b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Less,
+        (_, Value::Null) => Ordering::Greater,
+        _ if is_numeric(&a) && is_numeric(&b) => number(&a).total_cmp(&number(&b)),
+        _ => text(&a).to_lowercase().cmp(&text(&b).to_lowercase()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::airtable::{AirtableError, ListOptions, Record, Sort};
+
+    /// Serve `fixture` and return a client for it.
+    fn serve(fixture: Value) -> (FakeAirtable, Airtable) {
+        let fixture = serde_json::from_value(fixture).unwrap();
+        let fake = FakeAirtable::from_fixture("appFakeTest", fixture).unwrap();
+        let addr = fake.spawn("127.0.0.1:0".parse().unwrap()).unwrap();
+        let airtable = fake.client(addr).unwrap();
+        (fake, airtable)
+    }
+
+    fn numbers(n: usize) -> Value {
+        let records: Vec<Value> = (0..n)
+            .map(|i| json!({ "id": format!("rec{i:014}"), "fields": { "n": i } }))
+            .collect();
+        json!({ "tables": [{ "name": "numbers", "records": records }] })
+    }
+
+    fn record(fields: Value) -> Record<Value> {
+        Record {
+            id: String::new(),
+            fields,
+            created_time: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn names(records: &[Record<Value>]) -> Vec<&str> {
+        records
+            .iter()
+            .map(|r| r.fields["name"].as_str().unwrap_or_default())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn lists_every_page() {
+        let (_, airtable) = serve(numbers(25));
+        let options = ListOptions {
+            page_size: Some(10),
+            ..Default::default()
+        };
+
+        let records: Vec<Record<Value>> = airtable
+            .list_records("numbers", "", &options)
+            .await
+            .unwrap();
+        let n: Vec<u64> = records
+            .iter()
+            .map(|r| r.fields["n"].as_u64().unwrap())
+            .collect();
+        assert_eq!(n, (0..25).collect::<Vec<_>>());
+
+        let mut pages = airtable.pages::<Value>("numbers", "", options);
+        let mut sizes = Vec::new();
+        while let Some(page) = pages.next_page().await.unwrap() {
+            sizes.push(page.len());
+        }
+        assert_eq!(sizes, [10, 10, 5]);
+    }
+
+    #[test]
+    fn rejects_unknown_offsets() {
+        let fake =
+            FakeAirtable::from_fixture("appFakeTest", serde_json::from_value(numbers(5)).unwrap())
+                .unwrap();
+        let base = fake.base();
+        let query = |offset: &str| vec![("offset".to_string(), offset.to_string())];
+
+        assert_eq!(
+            base.list("numbers", &query("itr00000000000003")).unwrap()["records"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+        for offset in ["itr00000000000006", "rec00000000000001", ""] {
+            let e = base.list("numbers", &query(offset)).unwrap_err();
+            assert_eq!(
+                e.body["error"]["type"],
+                "LIST_RECORDS_ITERATOR_NOT_AVAILABLE"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_by_view_and_formula() {
+        let (_, airtable) = serve(json!({
+            "tables": [{
+                "name": "items",
+                "views": [{ "name": "Grid view" }, { "name": "Published", "filter": "{publish}" }],
+                "records": [
+                    { "fields": { "name": "racket", "price": 25, "publish": true } },
+                    { "fields": { "name": "mat", "price": 10.5, "publish": true } },
+                    { "fields": { "name": "board", "price": 5 } },
+                    { "fields": { "name": "mixer", "publish": true } },
+                ],
+            }],
+        }));
+        let options = ListOptions {
+            filter_by_formula: Some(Formula::field("price").less_than(20)),
+            sort: vec![Sort::asc("name")],
+            ..Default::default()
+        };
+
+        // A blank price counts as 0.
+        let published: Vec<Record<Value>> = airtable
+            .list_records("items", "Published", &options)
+            .await
+            .unwrap();
+        assert_eq!(names(&published), ["mat", "mixer"]);
+
+        let all: Vec<Record<Value>> = airtable
+            .list_records("items", "Grid view", &options)
+            .await
+            .unwrap();
+        assert_eq!(names(&all), ["board", "mat", "mixer"]);
+
+        let e = airtable
+            .list_records::<Value>("items", "Missing", &options)
+            .await
+            .unwrap_err();
+        assert_eq!(e.api_error().unwrap().type_, "VIEW_NAME_NOT_FOUND");
+    }
+
+    #[tokio::test]
+    async fn writes_at_most_ten_records() {
+        let fake =
+            FakeAirtable::from_fixture("appFakeTest", serde_json::from_value(numbers(0)).unwrap())
+                .unwrap();
+        let addr = fake.spawn("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let records: Vec<Value> = (0..11).map(|n| json!({ "fields": { "n": n } })).collect();
+        let resp = reqwest::Client::new()
+            .post(format!(
+                "{}appFakeTest/numbers",
+                FakeAirtable::endpoint(addr)
+            ))
+            .bearer_auth("fake")
+            .json(&json!({ "records": records }))
+            .send()
+            .await
+            .unwrap();
+
+        match AirtableError::from_response(resp).await {
+            AirtableError::InvalidRequest { status, error } => {
+                assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+                assert_eq!(error.type_, "INVALID_RECORDS");
+            }
+            e => panic!("unexpected error {e:?}"),
+        }
+        assert!(fake.snapshot().tables[0].records.is_empty());
+    }
+
+    #[tokio::test]
+    async fn upserts_on_the_merge_fields() {
+        let (fake, airtable) = serve(json!({
+            "tables": [{
+                "name": "items",
+                "records": [{ "id": "recExisting", "fields": { "name": "racket", "price": 25 } }],
+            }],
+        }));
+
+        let upserted = airtable
+            .upsert_records(
+                "items",
+                vec![
+                    record(json!({ "name": "racket", "price": 20 })),
+                    record(json!({ "name": "mat", "price": 10 })),
+                ],
+                &["name"],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(upserted.updated_records, ["recExisting"]);
+        assert_eq!(upserted.created_records.len(), 1);
+        assert_eq!(upserted.records[1].id, upserted.created_records[0]);
+
+        let records = &fake.snapshot().tables[0].records;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].fields["price"], 20);
+    }
+
+    #[tokio::test]
+    async fn deleting_a_missing_record_is_not_found() {
+        let (fake, airtable) = serve(numbers(1));
+
+        let e = airtable
+            .delete_records("numbers", ["rec00000000000000", "recMissing"])
+            .await
+            .unwrap_err();
+
+        assert!(matches!(e, AirtableError::NotFound(_)), "{e:?}");
+        assert!(e.is_missing_record());
+        // Nothing was deleted.
+        assert_eq!(fake.snapshot().tables[0].records.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn serves_the_end_to_end_fixture() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/end2end/fixtures/airtable.json"
+        );
+        let fake = FakeAirtable::load("appFakeUnregistry", path).unwrap();
+        let addr = fake.spawn("127.0.0.1:0".parse().unwrap()).unwrap();
+        let airtable = fake.client(addr).unwrap();
+
+        // What the registry lists.
+        let options = ListOptions {
+            filter_by_formula: Some(Formula::field("publish").equals(true)),
+            sort: vec![Sort::asc("name")],
+            ..Default::default()
+        };
+        let items: Vec<Record<Value>> = airtable
+            .list_records("items", "Grid view", &options)
+            .await
+            .unwrap();
+        assert_eq!(names(&items), ["stand mixer", "tennis racket", "yoga mat"]);
+
+        let tables = airtable.list_tables().await.unwrap();
+        let names: Vec<&str> = tables.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["categories", "items", "claims"]);
+    }
+
+    fn fields(fields: Value) -> FakeRecord {
+        FakeRecord {
+            id: "recEval".to_string(),
+            created_time: None,
+            fields: serde_json::from_value(fields).unwrap(),
+        }
+    }
+
+    fn matches(formula: &str, record: &FakeRecord) -> bool {
+        truthy(&eval(&formula.parse().unwrap(), record).unwrap())
+    }
+
+    #[test]
+    fn evaluates_comparisons() {
+        let record = fields(json!({ "name": "Racket", "price": 25, "publish": true }));
+
+        assert!(matches("{price} > 20", &record));
+        assert!(matches("{price} = '25'", &record));
+        assert!(!matches("{price} < 20", &record));
+        assert!(matches("{name} = 'Racket'", &record));
+        assert!(!matches("{name} = 'racket'", &record));
+        assert!(matches("{name} != 'Mat'", &record));
+        assert!(matches("{publish} = TRUE()", &record));
+        assert!(matches("{publish} = 1", &record));
+        assert!(matches("RECORD_ID() = 'recEval'", &record));
+    }
+
+    #[test]
+    fn treats_blank_as_empty_zero_and_false() {
+        let record = fields(json!({}));
+
+        assert!(matches("{missing} = ''", &record));
+        assert!(matches("{missing} = 0", &record));
+        assert!(matches("{missing} = FALSE()", &record));
+        assert!(matches("{missing} = BLANK()", &record));
+        assert!(!matches("{missing}", &record));
+        assert!(matches("NOT({missing})", &record));
+    }
+
+    #[test]
+    fn evaluates_logic_and_functions() {
+        let record = fields(json!({
+            "name": " Yoga Mat ",
+            "categories": ["sports", "home"],
+            "sold": "2023-10-01T12:00:00.000Z",
+        }));
+
+        assert!(matches("AND({name}, OR(FALSE(), TRUE()))", &record));
+        assert!(!matches("AND({name}, {missing})", &record));
+        assert!(matches("FIND('Mat', {name}) = 7", &record));
+        assert!(matches("FIND('mat', {name}) = 0", &record));
+        assert!(matches("SEARCH('mat', {name}) = 7", &record));
+        assert!(matches("LEN(TRIM({name})) = 8", &record));
+        assert!(matches("LOWER(TRIM({name})) = 'yoga mat'", &record));
+        assert!(matches("UPPER({categories}) = 'SPORTS, HOME'", &record));
+        assert!(matches("IS_AFTER({sold}, '2023-09-30')", &record));
+        assert!(matches("IS_BEFORE({sold}, '2023-10-02')", &record));
+        assert!(matches("IS_SAME({sold}, '2023-10-01', 'day')", &record));
+        assert!(!matches("IS_SAME({sold}, '2023-10-01', 'hour')", &record));
+        assert!(eval(&Formula::function("NOPE", Vec::<Formula>::new()), &record).is_err());
+    }
+
+    #[test]
+    fn joins_lists_in_formulas() {
+        let record = fields(json!({
+            "categories": ["sports", "home"],
+            "images": [{ "id": "att1", "url": "https://example.com/a.png", "filename": "a.png" }],
+        }));
+
+        assert_eq!(cell(record.fields.get("categories")), json!("sports, home"));
+        assert_eq!(cell(record.fields.get("images")), json!("a.png"));
+        assert!(matches(
+            &Formula::field("categories").has_choice("home").to_string(),
+            &record
+        ));
+        assert!(!matches(
+            &Formula::field("categories").has_choice("hom").to_string(),
+            &record
+        ));
+    }
+
+    #[test]
+    fn truthiness() {
+        for value in [json!(null), json!(false), json!(0), json!(""), json!([])] {
+            assert!(!truthy(&value), "{value}");
+        }
+        for value in [
+            json!(true),
+            json!(1),
+            json!(-0.5),
+            json!("0"),
+            json!(["a"]),
+            json!({}),
+        ] {
+            assert!(truthy(&value), "{value}");
+        }
+    }
+
+    #[test]
+    fn sorts_blank_first_then_by_value() {
+        let (blank, two, ten) = (None, Some(&json!(2)), Some(&json!(10)));
+        assert_eq!(order(blank, two), Ordering::Less);
+        assert_eq!(order(two, blank), Ordering::Greater);
+        assert_eq!(order(blank, blank), Ordering::Equal);
+        // Numbers as numbers, text ignoring case.
+        assert_eq!(order(two, ten), Ordering::Less);
+        assert_eq!(
+            order(Some(&json!("b")), Some(&json!("A"))),
+            Ordering::Greater
+        );
+        assert_eq!(order(Some(&json!("a")), Some(&json!("A"))), Ordering::Equal);
+    }
+}
@@ -0,0 +1,590 @@
+//! Types for the cell values of Airtable fields, for use in record types.
+//!
+//! Airtable leaves empty cells out of a record, so fields that can be empty
+//! should be an `Option` or have `#[serde(default)]`. The list types and
+//! [`Checkbox`] also accept an explicit `null`. Formula, rollup and lookup
+//! cells hold an error object instead of a value when the formula fails,
+//! which [`Computed`] keeps.
+//!
+//! FROM: https://airtable.com/developers/web/api/field-model
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use super::User;
+
+/// Deserialize `null` as the default value.
+fn or_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// The value of a currency field. The symbol is part of the field options.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(transparent)]
+pub struct Currency(pub f64);
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2}", self.0)
+    }
+}
+
+/// The value of a percent field, as a fraction: `0.25` is 25%.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(transparent)]
+pub struct Percent(pub f64);
+
+impl Percent {
+    /// The value in percent, e.g. `25.0`.
+    pub fn percent(&self) -> f64 {
+        self.0 * 100.0
+    }
+}
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.percent())
+    }
+}
+
+/// The value of a duration field, in seconds.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(transparent)]
+pub struct Duration(pub f64);
+
+impl Duration {
+    /// The duration as a `std::time::Duration`, negative durations are zero.
+    pub fn to_std(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(self.0.max(0.0))
+    }
+}
+
+impl From<std::time::Duration> for Duration {
+    fn from(duration: std::time::Duration) -> Self {
+        Duration(duration.as_secs_f64())
+    }
+}
+
+impl fmt::Display for Duration {
+    /// Formats as `h:mm:ss`, like Airtable's default duration format.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.0.abs().round() as u64;
+        let sign = if self.0 < 0.0 { "-" } else { "" };
+        write!(
+            f,
+            "{sign}{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
+
+/// The value of a rating field, from 1 to the maximum of the field.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(transparent)]
+pub struct Rating(pub u8);
+
+/// The value of a checkbox field. Airtable leaves unchecked boxes out of the
+/// record, so use it with `#[serde(default)]`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Checkbox(pub bool);
+
+impl Checkbox {
+    pub fn is_checked(&self) -> bool {
+        self.0
+    }
+}
+
+impl From<bool> for Checkbox {
+    fn from(checked: bool) -> Self {
+        Checkbox(checked)
+    }
+}
+
+impl<'de> Deserialize<'de> for Checkbox {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        or_default(deserializer).map(Checkbox)
+    }
+}
+
+/// The value of a single select field, the name of the chosen option.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct SingleSelect(pub String);
+
+impl From<&str> for SingleSelect {
+    fn from(choice: &str) -> Self {
+        SingleSelect(choice.to_string())
+    }
+}
+
+impl fmt::Display for SingleSelect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The value of a multiple select field, the names of the chosen options.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct MultipleSelects(pub Vec<String>);
+
+impl MultipleSelects {
+    /// Whether `choice` is one of the chosen options.
+    pub fn contains(&self, choice: &str) -> bool {
+        self.0.iter().any(|c| c == choice)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|c| c.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for MultipleSelects {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        or_default(deserializer).map(MultipleSelects)
+    }
+}
+
+/// The value of a linked record field, the ids of the linked records. Use
+/// [`Link`](super::Link) to fetch the linked records.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct RecordLinks(pub Vec<String>);
+
+impl RecordLinks {
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|id| id.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for RecordLinks {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        or_default(deserializer).map(RecordLinks)
+    }
+}
+
+/// The value of a formula or rollup cell, or of one value of a lookup.
+///
+/// A formula that fails, e.g. dividing by zero, holds an error object instead
+/// of a value: `{"error": "#ERROR!"}`, or `{"specialValue": "NaN"}` for
+/// numbers that aren't.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Computed<T> {
+    Value(T),
+    /// The error code, e.g. `#ERROR!`, or the special value, e.g. `NaN` or
+    /// `Infinity`.
+    Error(String),
+}
+
+/// The value of a rollup field.
+pub type Rollup<T> = Computed<T>;
+
+impl<T> Computed<T> {
+    pub fn value(&self) -> Option<&T> {
+        match self {
+            Computed::Value(value) => Some(value),
+            Computed::Error(_) => None,
+        }
+    }
+
+    pub fn into_value(self) -> Option<T> {
+        match self {
+            Computed::Value(value) => Some(value),
+            Computed::Error(_) => None,
+        }
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        match self {
+            Computed::Value(_) => None,
+            Computed::Error(error) => Some(error),
+        }
+    }
+}
+
+impl<'de, T: serde::de::DeserializeOwned> Deserialize<'de> for Computed<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+
+        if let Value::Object(object) = &value {
+            if object.len() == 1 {
+                let error = object.get("error").or_else(|| object.get("specialValue"));
+                if let Some(Value::String(error)) = error {
+                    return Ok(Computed::Error(error.to_string()));
+                }
+            }
+        }
+
+        T::deserialize(value)
+            .map(Computed::Value)
+            .map_err(D::Error::custom)
+    }
+}
+
+impl<T: Serialize> Serialize for Computed<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Computed::Value(value) => value.serialize(serializer),
+            Computed::Error(error) => serde_json::json!({ "error": error }).serialize(serializer),
+        }
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Computed<T> {
+    fn schema_name() -> String {
+        T::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        T::json_schema(gen)
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+}
+
+/// The value of a lookup field, the values of a field of the linked records.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Lookup<T>(pub Vec<Computed<T>>);
+
+impl<T> Default for Lookup<T> {
+    fn default() -> Self {
+        Lookup(Vec::new())
+    }
+}
+
+impl<T> Lookup<T> {
+    /// The values, leaving out the errors.
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.0.iter().filter_map(|v| v.value())
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(|v| v.error())
+    }
+}
+
+impl<'de, T: serde::de::DeserializeOwned> Deserialize<'de> for Lookup<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        or_default(deserializer).map(Lookup)
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Lookup<T> {
+    fn schema_name() -> String {
+        format!("Lookup_of_{}", T::schema_name())
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        Vec::<T>::json_schema(gen)
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+}
+
+/// The value of a created by field.
+pub type CreatedBy = User;
+/// The value of a last modified by field.
+pub type LastModifiedBy = User;
+
+/// The value of a last modified time or created time field.
+///
+/// Airtable sends a date without a time when the field is formatted as a date,
+/// and has sent times without a time zone, which are taken as UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct LastModifiedTime(pub DateTime<Utc>);
+
+impl<'de> Deserialize<'de> for LastModifiedTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+
+        DateTime::parse_from_rfc3339(&s)
+            .or_else(|_| DateTime::parse_from_rfc3339(&format!("{s}Z")))
+            .map(|t| t.with_timezone(&Utc))
+            .or_else(|_| {
+                NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+                    .map(|d| d.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+            })
+            .map(LastModifiedTime)
+            .map_err(|e| D::Error::custom(format!("invalid time {s:?}: {e}")))
+    }
+}
+
+/// The value of a rich text field, in Airtable's markdown.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct RichText(pub String);
+
+impl RichText {
+    /// The text without the markdown: links keep their text, and headings,
+    /// list markers, quotes and emphasis are dropped.
+    pub fn plain_text(&self) -> String {
+        self.0
+            .lines()
+            .map(|line| {
+                let line = line.trim_start();
+                let line = line.trim_start_matches('#').trim_start_matches('>');
+                let line = ["- ", "* ", "[ ] ", "[x] "]
+                    .iter()
+                    .fold(line.trim_start(), |l, marker| {
+                        l.strip_prefix(marker).unwrap_or(l)
+                    });
+                strip_markdown(line)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Drop the emphasis markers of a line and keep only the text of its links.
+///
+/// Markers are only dropped in pairs that open and close a word, so
+/// `snake_case` and `5*3` are left alone.
+fn strip_markdown(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut closing = Vec::new();
+    let mut text = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '*' | '_' | '~' | '`' => {
+                let run = delimiter_run(&chars, i);
+                if closing.contains(&i) {
+                    // Closes a pair opened before.
+                } else if let Some(end) = closing_delimiter(&chars, i, run) {
+                    closing.push(end);
+                } else {
+                    text.extend(&chars[i..i + run]);
+                }
+                i += run;
+                continue;
+            }
+            '\\' => {
+                text.extend(chars.get(i + 1));
+                i += 1;
+            }
+            // Skip the target of a link, `[text](url)`.
+            ']' if chars.get(i + 1) == Some(&'(') => {
+                while i < chars.len() && chars[i] != ')' {
+                    i += 1;
+                }
+            }
+            '[' if chars[i..].windows(2).any(|w| w == [']', '(']) => (),
+            c => text.push(c),
+        }
+        i += 1;
+    }
+
+    text
+}
+
+/// The number of times the delimiter at `start` is repeated, e.g. 2 for `**`.
+fn delimiter_run(chars: &[char], start: usize) -> usize {
+    chars[start..]
+        .iter()
+        .take_while(|&&c| c == chars[start])
+        .count()
+}
+
+/// Where the delimiter closing the one at `start` is, if it opens a word and
+/// a run as long closes one later in the line.
+fn closing_delimiter(chars: &[char], start: usize, run: usize) -> Option<usize> {
+    let before = start.checked_sub(1).map(|i| chars[i]);
+    let after = chars.get(start + run);
+    if before.is_some_and(|c| c.is_alphanumeric()) || after.is_none_or(|c| c.is_whitespace()) {
+        return None;
+    }
+
+    let mut i = start + run;
+    while i < chars.len() {
+        if chars[i] == '\\' {
+            i += 2;
+            continue;
+        }
+        if chars[i] != chars[start] {
+            i += 1;
+            continue;
+        }
+
+        let len = delimiter_run(chars, i);
+        let after = chars.get(i + len);
+        if len == run && !chars[i - 1].is_whitespace() && after.is_none_or(|c| !c.is_alphanumeric())
+        {
+            return Some(i);
+        }
+        i += len;
+    }
+
+    None
+}
+
+impl fmt::Display for RichText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The value of a button field.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Button {
+    #[serde(default)]
+    pub label: String,
+    /// The URL the button opens, if it opens one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// The value of an AI text field. The text is only there once it has been
+/// generated.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct AiText {
+    /// One of `empty`, `loading`, `generated` or `error`.
+    #[serde(default)]
+    pub state: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Whether the inputs changed since the text was generated.
+    #[serde(default, rename = "isStale")]
+    pub is_stale: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "errorType")]
+    pub error_type: Option<String>,
+}
+
+impl AiText {
+    /// The generated text, empty until there is one.
+    pub fn text(&self) -> &str {
+        self.value.as_deref().unwrap_or_default()
+    }
+}
+
+impl fmt::Display for AiText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Row {
+        #[serde(default)]
+        done: Checkbox,
+        #[serde(default)]
+        total: Option<Computed<f64>>,
+        #[serde(default)]
+        modified: Option<LastModifiedTime>,
+        #[serde(default)]
+        rating: Option<Rating>,
+        #[serde(default)]
+        duration: Option<Duration>,
+        #[serde(default)]
+        price: Option<Currency>,
+    }
+
+    fn row(fields: Value) -> Row {
+        serde_json::from_value(fields).unwrap()
+    }
+
+    #[test]
+    fn unchecked_boxes_are_left_out() {
+        assert!(!row(serde_json::json!({})).done.is_checked());
+        assert!(!row(serde_json::json!({"done": false})).done.is_checked());
+        assert!(!row(serde_json::json!({"done": null})).done.is_checked());
+        assert!(row(serde_json::json!({"done": true})).done.is_checked());
+    }
+
+    #[test]
+    fn computed_cells_keep_their_errors() {
+        let total = |v| row(serde_json::json!({ "total": v })).total.unwrap();
+        assert_eq!(total(serde_json::json!(2.5)), Computed::Value(2.5));
+        assert_eq!(
+            total(serde_json::json!({"error": "#ERROR!"})),
+            Computed::Error("#ERROR!".to_string())
+        );
+        assert_eq!(
+            total(serde_json::json!({"specialValue": "NaN"})).error(),
+            Some("NaN")
+        );
+        assert!(serde_json::from_value::<Row>(serde_json::json!({"total": {"value": 1}})).is_err());
+    }
+
+    #[test]
+    fn last_modified_times_default_to_utc() {
+        let modified = |v: &str| {
+            row(serde_json::json!({ "modified": v }))
+                .modified
+                .unwrap()
+                .0
+        };
+        let expected = Utc.with_ymd_and_hms(2023, 10, 2, 9, 30, 0).unwrap();
+        assert_eq!(modified("2023-10-02T09:30:00.000Z"), expected);
+        assert_eq!(modified("2023-10-02T09:30:00.000"), expected);
+        assert_eq!(modified("2023-10-02T11:30:00.000+02:00"), expected);
+        assert_eq!(
+            modified("2023-10-02"),
+            Utc.with_ymd_and_hms(2023, 10, 2, 0, 0, 0).unwrap()
+        );
+        assert!(
+            serde_json::from_value::<Row>(serde_json::json!({"modified": "yesterday"})).is_err()
+        );
+    }
+
+    #[test]
+    fn numbers_keep_their_shape() {
+        let r = row(serde_json::json!({"rating": 4, "duration": 3725.5, "price": 12.5}));
+        assert_eq!(r.rating, Some(Rating(4)));
+        assert_eq!(r.duration.unwrap().to_string(), "1:02:06");
+        assert_eq!(
+            r.duration.unwrap().to_std(),
+            std::time::Duration::from_millis(3_725_500)
+        );
+        assert_eq!(r.price.unwrap().to_string(), "12.50");
+
+        assert_eq!(Duration(-90.0).to_string(), "-0:01:30");
+        assert_eq!(Duration(-90.0).to_std(), std::time::Duration::ZERO);
+        assert!(serde_json::from_value::<Row>(serde_json::json!({"rating": 2.5})).is_err());
+        assert!(serde_json::from_value::<Row>(serde_json::json!({"price": "12.50"})).is_err());
+    }
+
+    #[test]
+    fn strips_paired_markdown_only() {
+        let plain = |s: &str| RichText(s.to_string()).plain_text();
+        assert_eq!(plain("**bold** and _italic_"), "bold and italic");
+        assert_eq!(plain("~~gone~~ `code`"), "gone code");
+        assert_eq!(plain("# A [link](https://example.com)"), "A link");
+        assert_eq!(plain("- a snake_case_name"), "a snake_case_name");
+        assert_eq!(plain("5*3 and 2 * 3"), "5*3 and 2 * 3");
+        assert_eq!(plain("_foo_bar_ x_"), "foo_bar x_");
+        assert_eq!(plain(r"\*not emphasis\*"), "*not emphasis*");
+        assert_eq!(plain("a[0] ** b"), "a[0] ** b");
+    }
+}
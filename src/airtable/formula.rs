@@ -0,0 +1,647 @@
+//! A typed builder for Airtable `filterByFormula` expressions.
+//!
+//! FROM: https://support.airtable.com/docs/formula-field-reference
+//!
+//! ```ignore
+//! use crate::airtable::Formula;
+//!
+//! // AND({publish} = TRUE(), FIND(", sports,", CONCATENATE(", ", {categories}, ",")))
+//! let filter = Formula::and([
+//!     Formula::field("publish").equals(true),
+//!     Formula::field("categories").has_choice("sports"),
+//! ]);
+//! ```
+use std::{fmt, str::FromStr};
+
+use chrono::{offset::Utc, DateTime};
+
+/// Comparison operators supported in Airtable formulas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    LessThan,
+    LessThanOrEqual,
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "!=",
+            Comparison::GreaterThan => ">",
+            Comparison::GreaterThanOrEqual => ">=",
+            Comparison::LessThan => "<",
+            Comparison::LessThanOrEqual => "<=",
+        })
+    }
+}
+
+/// An Airtable formula expression.
+///
+/// Rendering a formula with `to_string()` produces a string suitable for the
+/// `filterByFormula` query parameter, with field names and string literals
+/// quoted and escaped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Formula {
+    /// A reference to a field, rendered as `{name}`.
+    Field(String),
+    /// A string literal, rendered as `"value"`.
+    String(String),
+    /// A numeric literal. Airtable has no literal for NaN or infinity, they
+    /// render as `BLANK()`, so `{price} = NaN` matches the empty cells. Check
+    /// that a computed number is finite before filtering on it.
+    Number(f64),
+    /// A boolean literal, rendered as `TRUE()` or `FALSE()`.
+    Bool(bool),
+    /// A timestamp literal, rendered as an RFC 3339 string.
+    DateTime(DateTime<Utc>),
+    /// A binary comparison such as `{price} > 10`.
+    Compare(Box<Formula>, Comparison, Box<Formula>),
+    /// `AND(...)` of all the given expressions.
+    And(Vec<Formula>),
+    /// `OR(...)` of all the given expressions.
+    Or(Vec<Formula>),
+    /// `NOT(...)` of the given expression.
+    Not(Box<Formula>),
+    /// A call to any Airtable function, e.g. `FIND`, `IS_AFTER` or `RECORD_ID`.
+    Function(String, Vec<Formula>),
+    /// A pre-rendered formula that is passed through untouched.
+    Raw(String),
+}
+
+impl Formula {
+    /// Reference a field by name.
+    pub fn field<S: ToString>(name: S) -> Self {
+        Formula::Field(name.to_string())
+    }
+
+    /// A string literal.
+    pub fn string<S: ToString>(value: S) -> Self {
+        Formula::String(value.to_string())
+    }
+
+    /// A formula that is passed to Airtable as is.
+    pub fn raw<S: ToString>(formula: S) -> Self {
+        Formula::Raw(formula.to_string())
+    }
+
+    /// Call an arbitrary Airtable function.
+    pub fn function<S, I>(name: S, args: I) -> Self
+    where
+        S: ToString,
+        I: IntoIterator,
+        I::Item: Into<Formula>,
+    {
+        Formula::Function(name.to_string(), args.into_iter().map(Into::into).collect())
+    }
+
+    /// True when every expression is true. An empty list is always true.
+    pub fn and<I>(formulas: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Formula>,
+    {
+        Formula::And(formulas.into_iter().map(Into::into).collect())
+    }
+
+    /// True when any expression is true. An empty list is always false.
+    pub fn or<I>(formulas: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Formula>,
+    {
+        Formula::Or(formulas.into_iter().map(Into::into).collect())
+    }
+
+    /// Negate an expression.
+    pub fn not<F: Into<Formula>>(formula: F) -> Self {
+        Formula::Not(Box::new(formula.into()))
+    }
+
+    /// `RECORD_ID()`, the id of the record being evaluated.
+    pub fn record_id() -> Self {
+        Formula::function("RECORD_ID", Vec::<Formula>::new())
+    }
+
+    /// `BLANK()`, the empty value.
+    pub fn blank() -> Self {
+        Formula::function("BLANK", Vec::<Formula>::new())
+    }
+
+    fn compare<F: Into<Formula>>(self, op: Comparison, rhs: F) -> Self {
+        Formula::Compare(Box::new(self), op, Box::new(rhs.into()))
+    }
+
+    /// `self = rhs`
+    pub fn equals<F: Into<Formula>>(self, rhs: F) -> Self {
+        self.compare(Comparison::Equal, rhs)
+    }
+
+    /// `self != rhs`
+    pub fn not_equals<F: Into<Formula>>(self, rhs: F) -> Self {
+        self.compare(Comparison::NotEqual, rhs)
+    }
+
+    /// `self > rhs`
+    pub fn greater_than<F: Into<Formula>>(self, rhs: F) -> Self {
+        self.compare(Comparison::GreaterThan, rhs)
+    }
+
+    /// `self >= rhs`
+    pub fn greater_than_or_equal<F: Into<Formula>>(self, rhs: F) -> Self {
+        self.compare(Comparison::GreaterThanOrEqual, rhs)
+    }
+
+    /// `self < rhs`
+    pub fn less_than<F: Into<Formula>>(self, rhs: F) -> Self {
+        self.compare(Comparison::LessThan, rhs)
+    }
+
+    /// `self <= rhs`
+    pub fn less_than_or_equal<F: Into<Formula>>(self, rhs: F) -> Self {
+        self.compare(Comparison::LessThanOrEqual, rhs)
+    }
+
+    /// `FIND(needle, self)`, true when `needle` occurs anywhere in the value.
+    /// This is a substring match: on a multiple select field `"sports"` also
+    /// matches a `"winter sports"` option, use [`Formula::has_choice`] to
+    /// match whole options.
+    pub fn contains<F: Into<Formula>>(self, needle: F) -> Self {
+        Formula::function("FIND", [needle.into(), self])
+    }
+
+    /// True when a multiple select field, which formulas see as its options
+    /// joined by `", "`, has the option `choice`. Options that contain `", "`
+    /// themselves can't be told apart.
+    pub fn has_choice(self, choice: &str) -> Self {
+        Formula::function(
+            "FIND",
+            [
+                Formula::string(format!(", {choice},")),
+                Formula::function(
+                    "CONCATENATE",
+                    [Formula::string(", "), self, Formula::string(",")],
+                ),
+            ],
+        )
+    }
+
+    /// `IS_AFTER(self, date)`
+    pub fn is_after<F: Into<Formula>>(self, date: F) -> Self {
+        Formula::function("IS_AFTER", [self, date.into()])
+    }
+
+    /// `IS_BEFORE(self, date)`
+    pub fn is_before<F: Into<Formula>>(self, date: F) -> Self {
+        Formula::function("IS_BEFORE", [self, date.into()])
+    }
+
+    /// `IS_SAME(self, date, unit)`, where unit is e.g. `"day"` or `"month"`.
+    pub fn is_same<F: Into<Formula>>(self, date: F, unit: &str) -> Self {
+        Formula::function("IS_SAME", [self, date.into(), Formula::string(unit)])
+    }
+
+    /// `self = BLANK()`
+    pub fn is_blank(self) -> Self {
+        self.equals(Formula::blank())
+    }
+
+    /// `NOT(self = BLANK())`
+    pub fn is_not_blank(self) -> Self {
+        Formula::not(self.is_blank())
+    }
+}
+
+/// Write `args` separated by commas.
+fn write_args(f: &mut fmt::Formatter<'_>, args: &[Formula]) -> fmt::Result {
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{arg}")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Formula::Field(name) => {
+                f.write_str("{")?;
+                for c in name.chars() {
+                    match c {
+                        '}' => f.write_str("\\}")?,
+                        '\\' => f.write_str("\\\\")?,
+                        c => write!(f, "{c}")?,
+                    }
+                }
+                f.write_str("}")
+            }
+            Formula::String(value) => write_string(f, value),
+            Formula::Number(n) if n.is_finite() => write!(f, "{n}"),
+            // Airtable has no literal for NaN or infinity.
+            Formula::Number(_) => f.write_str("BLANK()"),
+            Formula::Bool(true) => f.write_str("TRUE()"),
+            Formula::Bool(false) => f.write_str("FALSE()"),
+            Formula::DateTime(date) => write_string(f, &date.to_rfc3339()),
+            Formula::Compare(lhs, op, rhs) => write!(f, "{} {op} {}", Parens(lhs), Parens(rhs)),
+            Formula::And(formulas) if formulas.is_empty() => f.write_str("TRUE()"),
+            Formula::Or(formulas) if formulas.is_empty() => f.write_str("FALSE()"),
+            Formula::And(formulas) => {
+                f.write_str("AND(")?;
+                write_args(f, formulas)?;
+                f.write_str(")")
+            }
+            Formula::Or(formulas) => {
+                f.write_str("OR(")?;
+                write_args(f, formulas)?;
+                f.write_str(")")
+            }
+            Formula::Not(formula) => write!(f, "NOT({formula})"),
+            Formula::Function(name, args) => {
+                write!(f, "{name}(")?;
+                write_args(f, args)?;
+                f.write_str(")")
+            }
+            Formula::Raw(formula) => f.write_str(formula),
+        }
+    }
+}
+
+/// Write a double quoted string literal, escaping quotes, backslashes and
+/// control characters.
+fn write_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Wraps nested comparisons and raw formulas in parentheses so operator
+/// precedence is never ambiguous.
+struct Parens<'a>(&'a Formula);
+
+impl fmt::Display for Parens<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Formula::Compare(..) | Formula::Raw(_) => write!(f, "({})", self.0),
+            formula => write!(f, "{formula}"),
+        }
+    }
+}
+
+impl From<&str> for Formula {
+    fn from(value: &str) -> Self {
+        Formula::String(value.to_string())
+    }
+}
+
+impl From<String> for Formula {
+    fn from(value: String) -> Self {
+        Formula::String(value)
+    }
+}
+
+impl From<&String> for Formula {
+    fn from(value: &String) -> Self {
+        Formula::String(value.clone())
+    }
+}
+
+impl From<bool> for Formula {
+    fn from(value: bool) -> Self {
+        Formula::Bool(value)
+    }
+}
+
+impl From<i32> for Formula {
+    fn from(value: i32) -> Self {
+        Formula::Number(value.into())
+    }
+}
+
+impl From<i64> for Formula {
+    fn from(value: i64) -> Self {
+        Formula::Number(value as f64)
+    }
+}
+
+/// NaN and infinity render as `BLANK()`, see [`Formula::Number`].
+impl From<f64> for Formula {
+    fn from(value: f64) -> Self {
+        Formula::Number(value)
+    }
+}
+
+impl From<DateTime<Utc>> for Formula {
+    fn from(value: DateTime<Utc>) -> Self {
+        Formula::DateTime(value)
+    }
+}
+
+/// A formula that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid formula at {position}: {message}")]
+pub struct ParseFormulaError {
+    /// The character the problem was found at.
+    pub position: usize,
+    pub message: String,
+}
+
+/// Parses the formulas [`Formula`] renders: fields, strings, numbers,
+/// comparisons and function calls. `AND`, `OR`, `NOT`, `TRUE` and `FALSE` are
+/// parsed into their own variants.
+impl FromStr for Formula {
+    type Err = ParseFormulaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            chars: s.chars().collect(),
+            position: 0,
+        };
+
+        let formula = parser.expression()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(formula),
+            Some(c) => Err(parser.error(format!("unexpected `{c}`"))),
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.position += 1;
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn error<S: ToString>(&self, message: S) -> ParseFormulaError {
+        ParseFormulaError {
+            position: self.position,
+            message: message.to_string(),
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseFormulaError> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(format!("expected `{expected}`, found `{c}`"))),
+            None => Err(self.error(format!("expected `{expected}`"))),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Formula, ParseFormulaError> {
+        let mut lhs = self.operand()?;
+
+        loop {
+            self.skip_whitespace();
+            let rest: String = self.chars[self.position..].iter().take(2).collect();
+            let (op, len) = if rest.starts_with("!=") {
+                (Comparison::NotEqual, 2)
+            } else if rest.starts_with(">=") {
+                (Comparison::GreaterThanOrEqual, 2)
+            } else if rest.starts_with("<=") {
+                (Comparison::LessThanOrEqual, 2)
+            } else if rest.starts_with('=') {
+                (Comparison::Equal, 1)
+            } else if rest.starts_with('>') {
+                (Comparison::GreaterThan, 1)
+            } else if rest.starts_with('<') {
+                (Comparison::LessThan, 1)
+            } else {
+                return Ok(lhs);
+            };
+            self.position += len;
+
+            let rhs = self.operand()?;
+            lhs = Formula::Compare(Box::new(lhs), op, Box::new(rhs));
+        }
+    }
+
+    fn operand(&mut self) -> Result<Formula, ParseFormulaError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let formula = self.expression()?;
+                self.expect(')')?;
+                Ok(formula)
+            }
+            Some('{') => {
+                self.position += 1;
+                self.delimited('}').map(Formula::Field)
+            }
+            Some(quote @ ('"' | '\'')) => {
+                self.position += 1;
+                self.delimited(quote).map(Formula::String)
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '.' => self.number(),
+            Some(c) if c.is_alphabetic() || c == '_' => self.call(),
+            Some(c) => Err(self.error(format!("unexpected `{c}`"))),
+            None => Err(self.error("unexpected end of formula")),
+        }
+    }
+
+    /// Read up to the unescaped `end`, the opening delimiter is already read.
+    fn delimited(&mut self, end: char) -> Result<String, ParseFormulaError> {
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                Some('\\') => match self.next() {
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some(c) => value.push(c),
+                    None => return Err(self.error("unexpected end of formula")),
+                },
+                Some(c) if c == end => return Ok(value),
+                Some(c) => value.push(c),
+                None => return Err(self.error(format!("expected `{end}`"))),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Formula, ParseFormulaError> {
+        let start = self.position;
+        if self.peek() == Some('-') {
+            self.position += 1;
+        }
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E')
+        {
+            self.position += 1;
+        }
+
+        let number: String = self.chars[start..self.position].iter().collect();
+        number
+            .parse()
+            .map(Formula::Number)
+            .map_err(|_| self.error(format!("invalid number `{number}`")))
+    }
+
+    fn call(&mut self) -> Result<Formula, ParseFormulaError> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.position += 1;
+        }
+        let name: String = self.chars[start..self.position].iter().collect();
+        self.expect('(')?;
+
+        let mut args = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(')') {
+            self.position += 1;
+        } else {
+            loop {
+                args.push(self.expression()?);
+                self.skip_whitespace();
+                match self.next() {
+                    Some(',') => (),
+                    Some(')') => break,
+                    _ => return Err(self.error("expected `,` or `)`")),
+                }
+            }
+        }
+
+        Ok(match (name.to_uppercase().as_str(), args.len()) {
+            ("TRUE", 0) => Formula::Bool(true),
+            ("FALSE", 0) => Formula::Bool(false),
+            ("AND", _) => Formula::And(args),
+            ("OR", _) => Formula::Or(args),
+            ("NOT", 1) => Formula::Not(Box::new(args.remove(0))),
+            (name, _) => Formula::Function(name.to_string(), args),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        let formula = Formula::string(r#"it's "quoted" \ back"#);
+        assert_eq!(formula.to_string(), r#""it's \"quoted\" \\ back""#);
+
+        let formula = Formula::string("line\nbreak\ttab");
+        assert_eq!(formula.to_string(), r#""line\nbreak\ttab""#);
+    }
+
+    #[test]
+    fn escapes_field_names() {
+        assert_eq!(Formula::field("sort order").to_string(), "{sort order}");
+        assert_eq!(Formula::field("a}b").to_string(), r"{a\}b}");
+        assert_eq!(Formula::field(r"a\b").to_string(), r"{a\\b}");
+    }
+
+    #[test]
+    fn renders_expressions() {
+        let formula = Formula::and([
+            Formula::field("publish").equals(true),
+            Formula::field("price").less_than(10),
+            Formula::not(Formula::field("name").is_blank()),
+        ]);
+        assert_eq!(
+            formula.to_string(),
+            "AND({publish} = TRUE(), {price} < 10, NOT({name} = BLANK()))"
+        );
+
+        assert_eq!(Formula::and(Vec::<Formula>::new()).to_string(), "TRUE()");
+        assert_eq!(Formula::or(Vec::<Formula>::new()).to_string(), "FALSE()");
+        assert_eq!(Formula::Number(f64::NAN).to_string(), "BLANK()");
+        assert_eq!(
+            Formula::field("price").equals(f64::INFINITY).to_string(),
+            "{price} = BLANK()"
+        );
+        assert_eq!(
+            Formula::field("a")
+                .equals(Formula::field("b").equals(1))
+                .to_string(),
+            "{a} = ({b} = 1)"
+        );
+    }
+
+    #[test]
+    fn contains_is_a_substring_match() {
+        assert_eq!(
+            Formula::field("categories").contains("sports").to_string(),
+            r#"FIND("sports", {categories})"#
+        );
+        assert_eq!(
+            Formula::field("categories")
+                .has_choice("sports")
+                .to_string(),
+            r#"FIND(", sports,", CONCATENATE(", ", {categories}, ","))"#
+        );
+    }
+
+    #[test]
+    fn round_trips_through_the_parser() {
+        let formulas = [
+            Formula::field("publish").equals(true),
+            Formula::field(r"odd } \ name").not_equals(Formula::string(r#"it's "x" \"#)),
+            Formula::or([
+                Formula::record_id().equals("rec1"),
+                Formula::record_id().equals("rec2"),
+            ]),
+            Formula::and([
+                Formula::field("price").greater_than_or_equal(2.5),
+                Formula::field("price").less_than_or_equal(-3),
+                Formula::not(Formula::field("name").is_blank()),
+            ]),
+            Formula::field("categories").has_choice("sports"),
+            Formula::string("tab\tnew\nline"),
+        ];
+
+        for formula in formulas {
+            let rendered = formula.to_string();
+            assert_eq!(rendered.parse::<Formula>(), Ok(formula), "{rendered}");
+        }
+    }
+
+    #[test]
+    fn parses_single_quoted_strings() {
+        assert_eq!(
+            "{name} != 'hidden'".parse::<Formula>(),
+            Ok(Formula::field("name").not_equals("hidden"))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_formulas() {
+        let e = "{name} = ".parse::<Formula>().unwrap_err();
+        assert_eq!(e.message, "unexpected end of formula");
+        assert!("FIND(\"a\"".parse::<Formula>().is_err());
+        assert!("{unclosed".parse::<Formula>().is_err());
+        assert!("1 2".parse::<Formula>().is_err());
+    }
+}
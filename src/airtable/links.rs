@@ -0,0 +1,311 @@
+//! Linked record fields that know the type of the records they link to, and
+//! fetching those records in batches.
+//!
+//! ```ignore
+//! #[derive(Deserialize)]
+//! struct Item {
+//!     #[serde(default)]
+//!     categories: Link<Category>,
+//! }
+//!
+//! let items: Vec<Record<Item>> = airtable.list_records("items", "", &options).await?;
+//! let categories = airtable
+//!     .resolve_links("categories", &items, |i| &i.categories, &Default::default())
+//!     .await?;
+//! for category in items[0].fields.categories.resolve(&categories) {
+//!     println!("{}", category.fields.name);
+//! }
+//! ```
+use std::{collections::HashMap, fmt, marker::PhantomData};
+
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{Airtable, Formula, ListOptions, Record, Result};
+
+/// The number of record ids looked up in one list request, which keeps the
+/// `filterByFormula` well under the URL length limit.
+const LINK_BATCH: usize = 50;
+
+/// The value of a linked record field, the ids of the linked records of type
+/// `T`.
+///
+/// Like [`RecordLinks`](super::RecordLinks) an empty cell is an empty list.
+pub struct Link<T> {
+    ids: Vec<String>,
+    linked: PhantomData<fn() -> T>,
+}
+
+impl<T> Link<T> {
+    pub fn new<I>(ids: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: ToString,
+    {
+        Link {
+            ids: ids.into_iter().map(|id| id.to_string()).collect(),
+            linked: PhantomData,
+        }
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.ids.iter().map(|id| id.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Whether the record with id `id` is linked.
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.iter().any(|i| i == id)
+    }
+
+    /// The linked records found in `records`, in the order of the links.
+    pub fn resolve<'a>(&self, records: &'a HashMap<String, Record<T>>) -> Vec<&'a Record<T>> {
+        self.ids.iter().filter_map(|id| records.get(id)).collect()
+    }
+}
+
+// Implemented by hand so they don't require `T` to implement them.
+
+impl<T> Default for Link<T> {
+    fn default() -> Self {
+        Link::new(Vec::<String>::new())
+    }
+}
+
+impl<T> Clone for Link<T> {
+    fn clone(&self) -> Self {
+        Link::new(&self.ids)
+    }
+}
+
+impl<T> PartialEq for Link<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ids == other.ids
+    }
+}
+
+impl<T> fmt::Debug for Link<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Link").field(&self.ids).finish()
+    }
+}
+
+impl<T> Serialize for Link<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.ids.serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Link<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let ids = Option::<Vec<String>>::deserialize(deserializer)?;
+        Ok(Link::new(ids.unwrap_or_default()))
+    }
+}
+
+impl<T> JsonSchema for Link<T> {
+    fn schema_name() -> String {
+        Vec::<String>::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        Vec::<String>::json_schema(gen)
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+}
+
+impl Airtable {
+    /// Fetch the records of `table` linked from `records` through the field
+    /// returned by `link`, keyed by record id.
+    ///
+    /// Every linked record is fetched once, however many records link to it,
+    /// with one list request per batch of ids matched by
+    /// `OR(RECORD_ID()="..", ..)`. The filter of `options` is added to that
+    /// formula, its `max_records` is ignored. Links to records that no longer
+    /// exist are left out.
+    pub async fn resolve_links<A, B, F>(
+        &self,
+        table: &str,
+        records: &[Record<A>],
+        link: F,
+        options: &ListOptions,
+    ) -> Result<HashMap<String, Record<B>>>
+    where
+        B: DeserializeOwned + Send + Unpin,
+        F: Fn(&A) -> &Link<B>,
+    {
+        let mut ids: Vec<&str> = records.iter().flat_map(|r| link(&r.fields).ids()).collect();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let mut linked = HashMap::new();
+        for chunk in ids.chunks(LINK_BATCH) {
+            let filter = Formula::or(
+                chunk
+                    .iter()
+                    .map(|id| Formula::record_id().equals(Formula::string(id))),
+            );
+            let filter = match &options.filter_by_formula {
+                Some(f) => Formula::and([filter, f.clone()]),
+                None => filter,
+            };
+
+            log::debug!(
+                "[airtable-api] Resolving {} linked records in table {}",
+                chunk.len(),
+                table
+            );
+            // Every record of the batch is wanted, whatever `max_records` is.
+            let options = ListOptions {
+                filter_by_formula: Some(filter),
+                max_records: None,
+                ..options.clone()
+            };
+            for record in self.list_records::<B>(table, "", &options).await? {
+                linked.insert(record.id.to_string(), record);
+            }
+        }
+
+        Ok(linked)
+    }
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        body::Body,
+        http::Request,
+        middleware::{self, Next},
+    };
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::airtable::fake::FakeAirtable;
+
+    #[derive(Deserialize)]
+    struct Item {
+        categories: Link<Value>,
+    }
+
+    /// Serve a table of `n` categories. Returns a client and the number of ids
+    /// looked up by each request.
+    fn serve(n: usize) -> (Airtable, Arc<Mutex<Vec<usize>>>) {
+        let records: Vec<Value> = (0..n)
+            .map(|i| json!({ "id": format!("rec{i:014}"), "fields": { "n": i } }))
+            .collect();
+        let fixture = json!({ "tables": [{ "name": "categories", "records": records }] });
+        let fake = FakeAirtable::from_fixture("appLinks", serde_json::from_value(fixture).unwrap())
+            .unwrap();
+
+        let lookups = Arc::new(Mutex::new(Vec::new()));
+        let seen = lookups.clone();
+        let app = fake.router().layer(middleware::from_fn(
+            move |request: Request<Body>, next: Next<Body>| {
+                let query = request.uri().query().unwrap_or_default();
+                seen.lock()
+                    .unwrap()
+                    .push(query.matches("RECORD_ID").count());
+                next.run(request)
+            },
+        ));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+
+        (fake.client(addr).unwrap(), lookups)
+    }
+
+    /// Items linking to the categories in `ids`, two at a time.
+    fn items(ids: &[String]) -> Vec<Record<Item>> {
+        ids.chunks(2)
+            .map(|ids| Record {
+                id: String::new(),
+                fields: Item {
+                    categories: Link::new(ids),
+                },
+                created_time: None,
+                warnings: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn ids(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|i| format!("rec{i:014}")).collect()
+    }
+
+    #[tokio::test]
+    async fn resolves_links_in_batches() {
+        let (airtable, lookups) = serve(120);
+
+        // Every category linked twice, and three that no longer exist.
+        let mut linked = ids(0..120);
+        linked.extend(ids(0..120));
+        linked.extend(ids(200..203));
+        let categories = airtable
+            .resolve_links(
+                "categories",
+                &items(&linked),
+                |i| &i.categories,
+                &Default::default(),
+            )
+            .await
+            .unwrap();
+
+        // Each id is looked up once.
+        assert_eq!(*lookups.lock().unwrap(), [50, 50, 23]);
+        assert_eq!(categories.len(), 120);
+        assert_eq!(categories["rec00000000000042"].fields["n"], 42);
+        assert!(!categories.contains_key("rec00000000000200"));
+
+        let resolved = Link::<Value>::new(["rec00000000000007", "rec00000000000200"]);
+        let resolved: Vec<_> = resolved
+            .resolve(&categories)
+            .iter()
+            .map(|r| &r.id)
+            .collect();
+        assert_eq!(resolved, ["rec00000000000007"]);
+    }
+
+    #[tokio::test]
+    async fn resolves_links_with_the_caller_options() {
+        let (airtable, lookups) = serve(60);
+        let items = items(&ids(0..60));
+
+        // The filter narrows the linked records down.
+        let options = ListOptions {
+            filter_by_formula: Some(Formula::field("n").less_than(10)),
+            ..Default::default()
+        };
+        let categories = airtable
+            .resolve_links::<_, Value, _>("categories", &items, |i| &i.categories, &options)
+            .await
+            .unwrap();
+        assert_eq!(categories.len(), 10);
+
+        // Unlike `max_records`, which would cut a batch short.
+        let options = ListOptions {
+            max_records: Some(5),
+            ..Default::default()
+        };
+        let categories = airtable
+            .resolve_links::<_, Value, _>("categories", &items, |i| &i.categories, &options)
+            .await
+            .unwrap();
+        assert_eq!(categories.len(), 60);
+        assert_eq!(*lookups.lock().unwrap(), [50, 10, 50, 10]);
+    }
+}
@@ -0,0 +1,729 @@
+//! The Airtable metadata API, describing the bases, tables, fields and views
+//! the API key has access to, and creating tables and fields.
+//!
+//! FROM: https://airtable.com/developers/web/api/get-base-schema
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::{error, Airtable, AirtableError, ApiError, Result};
+
+impl Airtable {
+    /// List the bases the API key can access.
+    /// FROM: https://airtable.com/developers/web/api/list-bases
+    pub async fn list_bases(&self) -> Result<Vec<Base>> {
+        let mut bases = Vec::new();
+        let mut offset = String::new();
+
+        loop {
+            let query = if offset.is_empty() {
+                None
+            } else {
+                Some(vec![("offset", offset)])
+            };
+
+            // Build the request.
+            let request = self.meta_request(Method::GET, "bases".to_string(), (), query)?;
+
+            let resp = self.client.execute(request).await?;
+            match resp.status() {
+                StatusCode::OK => (),
+                _ => return Err(AirtableError::from_response(resp).await),
+            };
+
+            // Try to deserialize the response.
+            let r: BasesResponse = error::json(resp).await?;
+
+            bases.extend(r.bases);
+            offset = r.offset;
+
+            if offset.is_empty() {
+                return Ok(bases);
+            }
+        }
+    }
+
+    /// List the tables in the base, with their fields and views.
+    /// FROM: https://airtable.com/developers/web/api/get-base-schema
+    pub async fn list_tables(&self) -> Result<Vec<TableSchema>> {
+        // Build the request.
+        let request = self.meta_request(
+            Method::GET,
+            format!("bases/{}/tables", self.base_id),
+            (),
+            None,
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: TablesResponse = error::json(resp).await?;
+
+        Ok(r.tables)
+    }
+
+    /// Get the schema of a table in the base by name or id.
+    pub async fn get_table_schema(&self, table: &str) -> Result<TableSchema> {
+        self.list_tables()
+            .await?
+            .into_iter()
+            .find(|t| t.name == table || t.id == table)
+            .ok_or_else(|| {
+                AirtableError::NotFound(ApiError {
+                    type_: "TABLE_NOT_FOUND".to_string(),
+                    message: format!("table {table} does not exist in base {}", self.base_id),
+                })
+            })
+    }
+}
+
+impl Airtable {
+    /// Create a table in the base. The first field becomes the primary field.
+    /// FROM: https://airtable.com/developers/web/api/create-table
+    pub async fn create_table(&self, table: &NewTable) -> Result<TableSchema> {
+        // Build the request.
+        let request = self.meta_request(
+            Method::POST,
+            format!("bases/{}/tables", self.base_id),
+            table,
+            None,
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: TableSchema = error::json(resp).await?;
+
+        Ok(r)
+    }
+
+    /// Create a field in a table.
+    /// FROM: https://airtable.com/developers/web/api/create-field
+    pub async fn create_field(&self, table_id: &str, field: &NewField) -> Result<FieldSchema> {
+        // Build the request.
+        let request = self.meta_request(
+            Method::POST,
+            format!("bases/{}/tables/{table_id}/fields", self.base_id),
+            field,
+            None,
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: FieldSchema = error::json(resp).await?;
+
+        Ok(r)
+    }
+
+    /// Rename a field or change its description.
+    /// FROM: https://airtable.com/developers/web/api/update-field
+    pub async fn update_field(
+        &self,
+        table_id: &str,
+        field_id: &str,
+        update: &FieldUpdate,
+    ) -> Result<FieldSchema> {
+        // Build the request.
+        let request = self.meta_request(
+            Method::PATCH,
+            format!("bases/{}/tables/{table_id}/fields/{field_id}", self.base_id),
+            update,
+            None,
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: FieldSchema = error::json(resp).await?;
+
+        Ok(r)
+    }
+
+    /// Make sure the base has the given tables and fields.
+    ///
+    /// Tables that do not exist are created, in order, and fields missing from
+    /// existing tables are added. Existing fields are left as they are, so
+    /// running this against a base that is already set up does nothing.
+    ///
+    /// A linked record field may give the name of the table it links to as
+    /// its `linked_table_id`, it is replaced with the id of that table.
+    pub async fn bootstrap(&self, tables: &[NewTable]) -> Result<Vec<TableSchema>> {
+        let mut existing = self.list_tables().await?;
+
+        for table in tables {
+            let resolve = |field: &NewField| {
+                let mut field = field.clone();
+                if let Some(options) = &mut field.options {
+                    if let Some(linked) = &options.linked_table_id {
+                        if let Some(t) = existing.iter().find(|t| &t.name == linked) {
+                            options.linked_table_id = Some(t.id.to_string());
+                        }
+                    }
+                }
+                field
+            };
+
+            match existing.iter().position(|t| t.name == table.name) {
+                None => {
+                    log::info!("[airtable-api] Creating table {}", table.name);
+
+                    let table = NewTable {
+                        fields: table.fields.iter().map(resolve).collect(),
+                        ..table.clone()
+                    };
+                    let created = self.create_table(&table).await?;
+                    existing.push(created);
+                }
+                Some(i) => {
+                    let missing: Vec<NewField> = table
+                        .fields
+                        .iter()
+                        .filter(|f| existing[i].field(&f.name).is_none())
+                        .map(resolve)
+                        .collect();
+
+                    for field in missing {
+                        log::info!(
+                            "[airtable-api] Creating field {} in table {}",
+                            field.name,
+                            table.name
+                        );
+
+                        let created = self.create_field(&existing[i].id, &field).await?;
+                        existing[i].fields.push(created);
+                    }
+                }
+            }
+        }
+
+        Ok(existing)
+    }
+}
+
+/// A table to create.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewTable {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The fields of the table. The first field becomes the primary field and
+    /// must be a type that can be primary, such as text or a number.
+    pub fields: Vec<NewField>,
+}
+
+impl NewTable {
+    pub fn new<S: ToString>(name: S, fields: Vec<NewField>) -> Self {
+        Self {
+            name: name.to_string(),
+            description: None,
+            fields,
+        }
+    }
+
+    pub fn description<S: ToString>(mut self, description: S) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+}
+
+/// A field to create.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_: FieldType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<FieldOptions>,
+}
+
+impl NewField {
+    /// A field without options, e.g. a text or email field.
+    pub fn new<S: ToString>(name: S, type_: FieldType) -> Self {
+        Self {
+            name: name.to_string(),
+            type_,
+            description: None,
+            options: None,
+        }
+    }
+
+    /// A field with options, e.g. a number field and its precision.
+    pub fn with_options<S: ToString>(name: S, type_: FieldType, options: FieldOptions) -> Self {
+        Self {
+            options: Some(options),
+            ..Self::new(name, type_)
+        }
+    }
+
+    /// A number field with `precision` decimal places.
+    pub fn number<S: ToString>(name: S, precision: u32) -> Self {
+        Self::with_options(
+            name,
+            FieldType::Number,
+            FieldOptions {
+                precision: Some(precision),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// A currency field.
+    pub fn currency<S: ToString>(name: S, symbol: &str, precision: u32) -> Self {
+        Self::with_options(
+            name,
+            FieldType::Currency,
+            FieldOptions {
+                precision: Some(precision),
+                symbol: Some(symbol.to_string()),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// A checkbox field.
+    pub fn checkbox<S: ToString>(name: S) -> Self {
+        Self::with_options(
+            name,
+            FieldType::Checkbox,
+            FieldOptions {
+                icon: Some("check".to_string()),
+                color: Some("greenBright".to_string()),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// A single or multiple select field with the given choices.
+    pub fn select<S: ToString>(name: S, type_: FieldType, choices: &[&str]) -> Self {
+        let mut options = FieldOptions {
+            choices: choices
+                .iter()
+                .map(|c| Choice {
+                    name: c.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        // Airtable requires the choices, even when there are none yet.
+        if options.choices.is_empty() {
+            options
+                .other
+                .insert("choices".to_string(), serde_json::Value::Array(vec![]));
+        }
+
+        Self::with_options(name, type_, options)
+    }
+
+    /// A linked record field, `table` is the id or, with [`Airtable::bootstrap`],
+    /// the name of the linked table.
+    pub fn link<S: ToString>(name: S, table: &str) -> Self {
+        Self::with_options(
+            name,
+            FieldType::MultipleRecordLinks,
+            FieldOptions {
+                linked_table_id: Some(table.to_string()),
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn description<S: ToString>(mut self, description: S) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+}
+
+/// The changes to make to a field.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct FieldUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BasesResponse {
+    #[serde(default)]
+    bases: Vec<Base>,
+    #[serde(default)]
+    offset: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TablesResponse {
+    #[serde(default)]
+    tables: Vec<TableSchema>,
+}
+
+/// A base the API key can access.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Base {
+    pub id: String,
+    pub name: String,
+    /// One of `none`, `read`, `comment`, `edit` or `create`.
+    #[serde(rename = "permissionLevel")]
+    pub permission_level: String,
+}
+
+/// A table in a base.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableSchema {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "primaryFieldId")]
+    pub primary_field_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<FieldSchema>,
+    #[serde(default)]
+    pub views: Vec<ViewSchema>,
+}
+
+impl TableSchema {
+    /// Find a field by name or id.
+    pub fn field(&self, name: &str) -> Option<&FieldSchema> {
+        self.fields.iter().find(|f| f.name == name || f.id == name)
+    }
+
+    /// Find a view by name or id.
+    pub fn view(&self, name: &str) -> Option<&ViewSchema> {
+        self.views.iter().find(|v| v.name == name || v.id == name)
+    }
+
+    /// The primary field of the table.
+    pub fn primary_field(&self) -> Option<&FieldSchema> {
+        self.field(&self.primary_field_id)
+    }
+}
+
+/// A field in a table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldSchema {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_: FieldType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<FieldOptions>,
+}
+
+/// The type of a field.
+/// FROM: https://airtable.com/developers/web/api/field-model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FieldType {
+    SingleLineText,
+    Email,
+    Url,
+    MultilineText,
+    Number,
+    Percent,
+    Currency,
+    SingleSelect,
+    MultipleSelects,
+    SingleCollaborator,
+    MultipleCollaborators,
+    MultipleRecordLinks,
+    Date,
+    DateTime,
+    PhoneNumber,
+    MultipleAttachments,
+    Checkbox,
+    Formula,
+    CreatedTime,
+    Rollup,
+    Count,
+    Lookup,
+    MultipleLookupValues,
+    AutoNumber,
+    Barcode,
+    Rating,
+    RichText,
+    Duration,
+    LastModifiedTime,
+    Button,
+    CreatedBy,
+    LastModifiedBy,
+    ExternalSyncSource,
+    AiText,
+    /// A field type this client does not know about yet.
+    #[serde(other)]
+    Unknown,
+}
+
+/// The options of a field. Which options are set depends on the field type,
+/// anything not modeled here is kept in `other`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct FieldOptions {
+    /// The options of a single or multiple select field.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<Choice>,
+    /// The number of decimal places of a number, currency, percent or
+    /// duration field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub precision: Option<u32>,
+    /// The currency symbol of a currency field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    /// The table a linked record field links to.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "linkedTableId"
+    )]
+    pub linked_table_id: Option<String>,
+    /// The field in the linked table that links back to this one.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "inverseLinkFieldId"
+    )]
+    pub inverse_link_field_id: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "prefersSingleRecordLink"
+    )]
+    pub prefers_single_record_link: Option<bool>,
+    /// The maximum value of a rating field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<u32>,
+    /// The icon of a checkbox or rating field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    /// The color of a checkbox or rating field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// The time zone of a date time field.
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "timeZone")]
+    pub time_zone: Option<String>,
+    /// The type of the values computed by a formula, rollup or lookup field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Box<FieldResult>>,
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A choice of a single or multiple select field.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Choice {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+/// The type of the values computed by a formula, rollup or lookup field.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldResult {
+    #[serde(rename = "type")]
+    pub type_: FieldType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<FieldOptions>,
+}
+
+/// A view of a table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewSchema {
+    pub id: String,
+    pub name: String,
+    /// One of `grid`, `form`, `calendar`, `gallery`, `kanban`, `timeline`
+    /// or `block`.
+    #[serde(rename = "type")]
+    pub type_: String,
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::airtable::fake::FakeAirtable;
+
+    fn serve(fake: &FakeAirtable) -> Airtable {
+        let addr = fake.spawn("127.0.0.1:0".parse().unwrap()).unwrap();
+        fake.client(addr).unwrap()
+    }
+
+    fn tables() -> Vec<NewTable> {
+        vec![
+            NewTable::new(
+                "categories",
+                vec![
+                    NewField::new("name", FieldType::SingleLineText),
+                    NewField::number("sort order", 0),
+                ],
+            ),
+            NewTable::new(
+                "items",
+                vec![
+                    NewField::new("name", FieldType::SingleLineText),
+                    NewField::currency("price", "$", 2),
+                    NewField::link("categories", "categories"),
+                    NewField::checkbox("publish"),
+                ],
+            ),
+            NewTable::new(
+                "claims",
+                vec![
+                    NewField::new("name", FieldType::SingleLineText),
+                    NewField::link("item", "items"),
+                ],
+            ),
+        ]
+    }
+
+    /// Every table with the ids of its fields.
+    fn field_ids(tables: &[TableSchema]) -> Vec<(String, Vec<String>)> {
+        tables
+            .iter()
+            .map(|t| {
+                (
+                    t.name.to_string(),
+                    t.fields.iter().map(|f| f.id.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn bootstraps_an_empty_base_once() {
+        let fake = FakeAirtable::new("appMetaTest");
+        let airtable = serve(&fake);
+
+        let created = airtable.bootstrap(&tables()).await.unwrap();
+        let names: Vec<_> = created.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["categories", "items", "claims"]);
+        let items = &created[1];
+        assert_eq!(items.primary_field().unwrap().name, "name");
+        assert_eq!(items.field("price").unwrap().type_, FieldType::Currency);
+
+        // Everything exists, nothing is created the second time.
+        let again = airtable.bootstrap(&tables()).await.unwrap();
+        assert_eq!(field_ids(&again), field_ids(&created));
+        assert_eq!(
+            field_ids(&airtable.list_tables().await.unwrap()),
+            field_ids(&created)
+        );
+    }
+
+    #[tokio::test]
+    async fn bootstrap_adds_missing_fields() {
+        let fixture = json!({ "tables": [{
+            "name": "items",
+            "records": [{ "fields": { "name": "yoga mat", "price": 10.5 } }]
+        }] });
+        let fake =
+            FakeAirtable::from_fixture("appMetaTest", serde_json::from_value(fixture).unwrap())
+                .unwrap();
+        let airtable = serve(&fake);
+        let before = airtable.get_table_schema("items").await.unwrap();
+
+        let tables = airtable.bootstrap(&tables()).await.unwrap();
+        let items = tables.iter().find(|t| t.name == "items").unwrap();
+        let fields: Vec<_> = items.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(fields, ["name", "price", "categories", "publish"]);
+        // The existing fields are left as they were.
+        assert_eq!(before.fields.len(), 2);
+        for field in &before.fields {
+            assert_eq!(items.field(&field.name).unwrap().id, field.id);
+        }
+    }
+
+    #[tokio::test]
+    async fn links_resolve_tables_by_name() {
+        let fake = FakeAirtable::new("appMetaTest");
+        let airtable = serve(&fake);
+
+        let tables = airtable.bootstrap(&tables()).await.unwrap();
+        let linked = |table: usize, field: &str| {
+            tables[table]
+                .field(field)
+                .and_then(|f| f.options.as_ref())
+                .and_then(|o| o.linked_table_id.clone())
+        };
+        assert_eq!(linked(1, "categories"), Some(tables[0].id.to_string()));
+        assert_eq!(linked(2, "item"), Some(tables[1].id.to_string()));
+
+        // A table that doesn't exist isn't resolved, and Airtable wants ids.
+        let wishes = NewTable::new(
+            "wishes",
+            vec![
+                NewField::new("name", FieldType::SingleLineText),
+                NewField::link("wisher", "people"),
+            ],
+        );
+        match airtable.bootstrap(&[wishes]).await {
+            Err(AirtableError::InvalidRequest { .. }) => (),
+            r => panic!("expected an invalid request, got {r:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn creates_and_updates_fields() {
+        let fake = FakeAirtable::new("appMetaTest");
+        let airtable = serve(&fake);
+        let table = airtable
+            .create_table(&NewTable::new(
+                "items",
+                vec![NewField::new("name", FieldType::SingleLineText)],
+            ))
+            .await
+            .unwrap();
+
+        let field = airtable
+            .create_field(&table.id, &NewField::new("notes", FieldType::MultilineText))
+            .await
+            .unwrap();
+        let updated = airtable
+            .update_field(
+                &table.id,
+                &field.id,
+                &FieldUpdate {
+                    name: Some("description".to_string()),
+                    description: Some("Shown under the name.".to_string()),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(updated.id, field.id);
+
+        let table = airtable.get_table_schema("items").await.unwrap();
+        let field = table.field("description").unwrap();
+        assert_eq!(field.type_, FieldType::MultilineText);
+        assert_eq!(field.description.as_deref(), Some("Shown under the name."));
+
+        // Field names are unique within a table.
+        let duplicate = airtable
+            .create_field(&table.id, &NewField::new("name", FieldType::Email))
+            .await;
+        assert!(matches!(
+            duplicate,
+            Err(AirtableError::InvalidRequest { .. })
+        ));
+    }
+}
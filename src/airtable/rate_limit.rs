@@ -0,0 +1,257 @@
+//! Client side rate limiting for the Airtable API.
+//!
+//! Airtable allows 5 requests per second per base. Going over returns a 429
+//! and locks the base out for 30 seconds, so every request to a base waits
+//! until fewer than 5 were sent to it in the last second by all clients in
+//! the process, and a 429 pauses the whole base until the lockout is over.
+//! The retry middleware then resends the rejected request once the pause
+//! ends.
+//!
+//! FROM: https://airtable.com/developers/web/api/rate-limits
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use reqwest::{header, Request, Response, StatusCode};
+use reqwest_middleware::{Middleware, Next};
+use task_local_extensions::Extensions;
+
+use super::error::parse_retry_after;
+
+/// The number of requests Airtable allows per second per base.
+pub const REQUESTS_PER_SECOND: usize = 5;
+
+/// How long Airtable locks a base out after a 429 that has no `Retry-After`.
+pub const LOCKOUT: Duration = Duration::from_secs(30);
+
+/// The window [`REQUESTS_PER_SECOND`] is counted over.
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// The requests sent to a single base in the last second, so no second ever
+/// sees more than [`REQUESTS_PER_SECOND`] of them.
+#[derive(Debug, Default)]
+struct Window {
+    sent: VecDeque<Instant>,
+    locked_until: Option<Instant>,
+}
+
+impl Window {
+    /// Count a request sent at `now`, or return how long to wait before trying
+    /// again.
+    fn try_acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        if let Some(locked_until) = self.locked_until {
+            if locked_until > now {
+                return Err(locked_until - now);
+            }
+            self.locked_until = None;
+        }
+
+        while self
+            .sent
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) >= WINDOW)
+        {
+            self.sent.pop_front();
+        }
+
+        match self.sent.front() {
+            Some(oldest) if self.sent.len() >= REQUESTS_PER_SECOND => Err(*oldest + WINDOW - now),
+            _ => {
+                self.sent.push_back(now);
+                Ok(())
+            }
+        }
+    }
+
+    /// Stop letting requests through until `until`.
+    fn lock(&mut self, until: Instant) {
+        if self.locked_until.is_none_or(|l| l < until) {
+            self.locked_until = Some(until);
+        }
+    }
+}
+
+/// Rate limits requests to a single Airtable base.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    base_id: String,
+    window: Arc<Mutex<Window>>,
+}
+
+impl RateLimiter {
+    /// The limiter for `base_id`. Every limiter for the same base shares one
+    /// window, so clients created per request still respect the limit together.
+    pub fn for_base(base_id: &str) -> Self {
+        static WINDOWS: OnceLock<Mutex<HashMap<String, Arc<Mutex<Window>>>>> = OnceLock::new();
+
+        let mut windows = WINDOWS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let window = windows.entry(base_id.to_string()).or_default().clone();
+
+        Self {
+            base_id: base_id.to_string(),
+            window,
+        }
+    }
+
+    /// Wait until a request may be sent to the base.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+                match window.try_acquire(Instant::now()) {
+                    Ok(()) => return,
+                    Err(wait) => wait,
+                }
+            };
+
+            log::debug!(
+                "[airtable-api] Rate limiting base {}, waiting {:?}",
+                self.base_id,
+                wait
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Pause all requests to the base for `duration`.
+    pub fn penalize(&self, duration: Duration) {
+        log::warn!(
+            "[airtable-api] Base {} was rate limited, pausing requests for {:?}",
+            self.base_id,
+            duration
+        );
+
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        window.lock(Instant::now() + duration);
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for RateLimiter {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        self.acquire().await;
+
+        let resp = next.run(req, extensions).await?;
+
+        if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = resp
+                .headers()
+                .get(header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| parse_retry_after(v, Utc::now()))
+                .unwrap_or(LOCKOUT);
+
+            self.penalize(retry_after);
+        }
+
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use axum::{routing::get, Router};
+
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn lets_five_requests_through_per_second() {
+        let start = Instant::now();
+        let mut window = Window::default();
+
+        for _ in 0..REQUESTS_PER_SECOND {
+            assert_eq!(window.try_acquire(start), Ok(()));
+        }
+        assert_eq!(window.try_acquire(start), Err(WINDOW));
+        assert_eq!(window.try_acquire(start + 999 * MS), Err(MS));
+        assert_eq!(window.try_acquire(start + WINDOW), Ok(()));
+    }
+
+    #[test]
+    fn never_lets_more_than_five_through_in_any_second() {
+        let start = Instant::now();
+        let mut window = Window::default();
+
+        // Send as fast as the window allows for 3 seconds.
+        let mut sent = vec![];
+        let mut now = start;
+        while now < start + 3 * WINDOW {
+            match window.try_acquire(now) {
+                Ok(()) => sent.push(now),
+                Err(wait) => now += wait,
+            }
+        }
+
+        assert_eq!(sent.len(), 3 * REQUESTS_PER_SECOND);
+        for (i, first) in sent.iter().enumerate() {
+            let in_window = sent[i..]
+                .iter()
+                .take_while(|s| s.duration_since(*first) < WINDOW)
+                .count();
+            assert!(
+                in_window <= REQUESTS_PER_SECOND,
+                "{in_window} requests in a second"
+            );
+        }
+    }
+
+    #[test]
+    fn waits_out_a_lockout() {
+        let start = Instant::now();
+        let mut window = Window::default();
+
+        window.lock(start + LOCKOUT);
+        assert_eq!(window.try_acquire(start + WINDOW), Err(LOCKOUT - WINDOW));
+
+        // A shorter lockout doesn't end the longer one.
+        window.lock(start + WINDOW);
+        assert_eq!(window.try_acquire(start + WINDOW), Err(LOCKOUT - WINDOW));
+
+        assert_eq!(window.try_acquire(start + LOCKOUT), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn pauses_the_base_after_a_429() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route(
+            "/",
+            get(|| async { (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, "5")]) }),
+        );
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+
+        let limiter = RateLimiter::for_base("appRateLimitTest");
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(limiter.clone())
+            .build();
+        let resp = client.get(format!("http://{addr}/")).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Every limiter for the base waits out the Retry-After.
+        let wait = RateLimiter::for_base("appRateLimitTest")
+            .window
+            .lock()
+            .unwrap()
+            .try_acquire(Instant::now())
+            .unwrap_err();
+        assert!(wait > Duration::from_secs(4) && wait <= Duration::from_secs(5));
+    }
+}
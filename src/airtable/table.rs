@@ -0,0 +1,142 @@
+//! A handle on a table that knows its record type, view and list options, so
+//! they aren't repeated at every call.
+//!
+//! ```ignore
+//! let items = airtable.table::<Item>("items").view("Grid view");
+//! let published = items
+//!     .list_with(&ListOptions {
+//!         filter_by_formula: Some(Formula::field("publish").equals(true)),
+//!         ..Default::default()
+//!     })
+//!     .await?;
+//! ```
+use std::{collections::HashMap, marker::PhantomData};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{Airtable, Comment, Deleted, Link, ListOptions, Pages, Record, Result, Upserted};
+
+/// A table holding records of type `T`, see [`Airtable::table`].
+pub struct Table<'a, T> {
+    airtable: &'a Airtable,
+    name: String,
+    view: String,
+    options: ListOptions,
+    record: PhantomData<fn() -> T>,
+}
+
+impl Airtable {
+    /// A handle on the table `name`, by name or id, whose records are `T`.
+    /// Records are listed from every view until [`Table::view`] is set.
+    pub fn table<T>(&self, name: &str) -> Table<'_, T> {
+        Table {
+            airtable: self,
+            name: name.to_string(),
+            view: String::new(),
+            options: ListOptions::default(),
+            record: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> Table<'a, T> {
+    /// List the records of `view` instead of every record.
+    pub fn view(mut self, view: &str) -> Self {
+        self.view = view.to_string();
+        self
+    }
+
+    /// The options records are listed with when none are given.
+    pub fn options(mut self, options: ListOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn view_name(&self) -> &str {
+        &self.view
+    }
+
+    pub fn airtable(&self) -> &'a Airtable {
+        self.airtable
+    }
+}
+
+impl<'a, T: DeserializeOwned + Send + Unpin + 'a> Table<'a, T> {
+    /// List the records with the default options.
+    pub async fn list(&self) -> Result<Vec<Record<T>>> {
+        self.list_with(&self.options).await
+    }
+
+    /// List the records with other options than the default ones.
+    pub async fn list_with(&self, options: &ListOptions) -> Result<Vec<Record<T>>> {
+        self.airtable
+            .list_records(&self.name, &self.view, options)
+            .await
+    }
+
+    /// Page through the records with the default options.
+    pub fn pages(&self) -> Pages<'a, T> {
+        self.airtable
+            .pages(&self.name, &self.view, self.options.clone())
+    }
+
+    pub async fn get(&self, record_id: &str) -> Result<Record<T>> {
+        self.airtable.get_record(&self.name, record_id).await
+    }
+
+    /// Fetch the records of this table linked from `records`, see
+    /// [`Airtable::resolve_links`]. The view is not used, the default filter
+    /// is.
+    pub async fn resolve<A, F>(
+        &self,
+        records: &[Record<A>],
+        link: F,
+    ) -> Result<HashMap<String, Record<T>>>
+    where
+        F: Fn(&A) -> &Link<T>,
+    {
+        self.airtable
+            .resolve_links(&self.name, records, link, &self.options)
+            .await
+    }
+
+    /// The comments on a record, newest first.
+    pub async fn comments(&self, record_id: &str) -> Result<Vec<Comment>> {
+        self.airtable.list_comments(&self.name, record_id).await
+    }
+
+    pub async fn delete<'b>(
+        &self,
+        record_ids: impl IntoIterator<Item = &'b str>,
+    ) -> Result<Vec<Deleted>> {
+        self.airtable.delete_records(&self.name, record_ids).await
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Table<'_, T> {
+    /// See [`Airtable::create_records`].
+    pub async fn create(&self, records: Vec<Record<T>>) -> Result<Vec<Record<T>>> {
+        self.airtable.create_records(&self.name, records).await
+    }
+
+    /// See [`Airtable::update_records`].
+    pub async fn update(&self, records: Vec<Record<T>>) -> Result<Vec<Record<T>>> {
+        self.airtable.update_records(&self.name, records).await
+    }
+
+    /// See [`Airtable::replace_records`].
+    pub async fn replace(&self, records: Vec<Record<T>>) -> Result<Vec<Record<T>>> {
+        self.airtable.replace_records(&self.name, records).await
+    }
+
+    /// See [`Airtable::upsert_records`].
+    pub async fn upsert(&self, records: Vec<Record<T>>, merge_on: &[&str]) -> Result<Upserted<T>> {
+        self.airtable
+            .upsert_records(&self.name, records, merge_on)
+            .await
+    }
+}
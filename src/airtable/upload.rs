@@ -0,0 +1,315 @@
+//! Uploading files straight into an attachment field, without hosting them at
+//! a public URL first.
+//!
+//! FROM: https://airtable.com/developers/web/api/upload-attachment
+use std::{collections::HashMap, io};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::{stream, Stream, StreamExt};
+use reqwest::{header, StatusCode};
+use serde::Deserialize;
+
+use super::{error, Airtable, AirtableError, ApiError, Attachment, Result};
+
+/// The largest file Airtable accepts through the upload endpoint.
+pub const MAX_UPLOAD_SIZE: u64 = 5 * 1024 * 1024;
+
+impl Airtable {
+    /// Upload a file into an attachment field of a record, adding it to the
+    /// attachments already there. `field` is the name or id of the field.
+    ///
+    /// The content is read from `content` while the request is sent, `len` must
+    /// be its exact size in bytes. Airtable accepts files up to
+    /// [`MAX_UPLOAD_SIZE`], larger files are refused before anything is sent;
+    /// link them by URL instead.
+    ///
+    /// Streamed uploads are not retried.
+    pub async fn upload_attachment<S, B>(
+        &self,
+        record_id: &str,
+        field: &str,
+        filename: &str,
+        content_type: &str,
+        len: u64,
+        content: S,
+    ) -> Result<Attachment>
+    where
+        S: Stream<Item = io::Result<B>> + Send + Sync + 'static,
+        B: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        if len > MAX_UPLOAD_SIZE {
+            return Err(AirtableError::InvalidRequest {
+                status: StatusCode::PAYLOAD_TOO_LARGE,
+                error: ApiError {
+                    type_: "ATTACHMENT_TOO_LARGE".to_string(),
+                    message: format!(
+                        "{filename} is {len} bytes, uploads are limited to {MAX_UPLOAD_SIZE}"
+                    ),
+                },
+            });
+        }
+
+        // The body is JSON with the file in base64, which we encode as the
+        // content comes in.
+        let prefix = format!(
+            "{{\"contentType\":{},\"filename\":{},\"file\":\"",
+            serde_json::Value::from(content_type),
+            serde_json::Value::from(filename),
+        );
+        let suffix = "\"}";
+        let body_len = prefix.len() as u64 + encoded_len(len) + suffix.len() as u64;
+
+        let url = self
+            .content_endpoint
+            .join(&format!(
+                "{}/{record_id}/{field}/uploadAttachment",
+                self.base_id
+            ))
+            .map_err(|e| AirtableError::Config(e.to_string()))?;
+
+        let body = stream::once(async move { Ok(prefix.into_bytes()) })
+            .chain(base64_stream(content, len, suffix));
+
+        let resp = self
+            .once_client
+            .post(url)
+            .bearer_auth(&self.key)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, body_len)
+            .body(reqwest::Body::wrap_stream(body))
+            .send()
+            .await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: UploadResponse = error::json(resp).await?;
+
+        // The new attachment is added after the existing ones.
+        let attachments: Vec<Attachment> = r.fields.into_values().flatten().collect();
+        attachments
+            .iter()
+            .rposition(|a| a.filename == filename)
+            .or_else(|| attachments.len().checked_sub(1))
+            .map(|i| attachments[i].clone())
+            .ok_or_else(|| {
+                AirtableError::NotFound(ApiError {
+                    type_: "ATTACHMENT_NOT_FOUND".to_string(),
+                    message: format!("the upload of {filename} returned no attachment"),
+                })
+            })
+    }
+
+    /// Upload a file held in memory into an attachment field of a record.
+    pub async fn upload_attachment_bytes(
+        &self,
+        record_id: &str,
+        field: &str,
+        filename: &str,
+        content_type: &str,
+        content: Vec<u8>,
+    ) -> Result<Attachment> {
+        let len = content.len() as u64;
+        self.upload_attachment(
+            record_id,
+            field,
+            filename,
+            content_type,
+            len,
+            stream::once(async move { Ok(content) }),
+        )
+        .await
+    }
+
+    /// Upload a file on disk into an attachment field of a record. The content
+    /// type is guessed from the extension.
+    pub async fn upload_attachment_file(
+        &self,
+        record_id: &str,
+        field: &str,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Attachment> {
+        use tokio::io::AsyncReadExt;
+
+        let path = path.as_ref();
+        let filename = path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let content_type = content_type_for(path);
+
+        let file = tokio::fs::File::open(path).await?;
+        let len = file.metadata().await?.len();
+
+        let content = stream::unfold(file, |mut file| async move {
+            let mut buf = vec![0; 64 * 1024];
+            match file.read(&mut buf).await {
+                Ok(0) => None,
+                Ok(n) => {
+                    buf.truncate(n);
+                    Some((Ok(buf), file))
+                }
+                Err(e) => Some((Err(e), file)),
+            }
+        });
+
+        self.upload_attachment(record_id, field, &filename, content_type, len, content)
+            .await
+    }
+}
+
+/// The length of `len` bytes encoded in padded base64.
+fn encoded_len(len: u64) -> u64 {
+    len.div_ceil(3) * 4
+}
+
+/// Encode `content` in base64 as it comes in, followed by `suffix`. Fails if
+/// `content` isn't `len` bytes, since the length of the body was sent already.
+fn base64_stream<S, B>(
+    content: S,
+    len: u64,
+    suffix: &'static str,
+) -> impl Stream<Item = io::Result<Vec<u8>>> + Send + Sync
+where
+    S: Stream<Item = io::Result<B>> + Send + Sync + 'static,
+    B: AsRef<[u8]> + Send + Sync + 'static,
+{
+    let wrong_len = move |read: u64| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the file to upload is {read} bytes, not {len}"),
+        )
+    };
+
+    // Base64 encodes 3 bytes at a time, so what doesn't fit in a group of 3 is
+    // kept for the next chunk.
+    let state = (Box::pin(content), Vec::new(), 0, false);
+    stream::unfold(
+        state,
+        move |(mut content, mut rest, mut read, done)| async move {
+            if done {
+                return None;
+            }
+
+            match content.next().await {
+                Some(Ok(chunk)) => {
+                    let chunk = chunk.as_ref();
+                    read += chunk.len() as u64;
+                    if read > len {
+                        return Some((Err(wrong_len(read)), (content, rest, read, true)));
+                    }
+
+                    rest.extend_from_slice(chunk);
+                    let whole = rest.len() - rest.len() % 3;
+                    let encoded = STANDARD.encode(&rest[..whole]);
+                    rest.drain(..whole);
+                    Some((Ok(encoded.into_bytes()), (content, rest, read, false)))
+                }
+                Some(Err(e)) => Some((Err(e), (content, rest, read, true))),
+                None if read < len => Some((Err(wrong_len(read)), (content, rest, read, true))),
+                None => {
+                    let encoded = STANDARD.encode(&rest) + suffix;
+                    Some((Ok(encoded.into_bytes()), (content, Vec::new(), read, true)))
+                }
+            }
+        },
+    )
+}
+
+/// Guess the content type of a file from its extension.
+fn content_type_for(path: &std::path::Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "heic" => "image/heic",
+        "heif" => "image/heif",
+        "pdf" => "application/pdf",
+        "mp4" => "video/mp4",
+        "mov" => "video/quicktime",
+        _ => "application/octet-stream",
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct UploadResponse {
+    /// The attachments of the field, keyed by field id.
+    #[serde(default)]
+    fields: HashMap<String, Vec<Attachment>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    /// Encode `data` split into chunks of `chunk` bytes.
+    async fn encode(data: &[u8], chunk: usize) -> io::Result<Vec<u8>> {
+        let chunks: Vec<io::Result<Vec<u8>>> = data.chunks(chunk).map(|c| Ok(c.to_vec())).collect();
+        base64_stream(stream::iter(chunks), data.len() as u64, "\"}")
+            .try_concat()
+            .await
+    }
+
+    #[tokio::test]
+    async fn encodes_across_chunk_boundaries() {
+        for len in [0, 1, 2, 3, 4, 5, 6, 7, 10, 299, 300, 301] {
+            let data: Vec<u8> = (0..len).map(|i| (i * 7 % 256) as u8).collect();
+            let expected = STANDARD.encode(&data) + "\"}";
+
+            for chunk in [1, 2, 3, 4, 5, 64] {
+                let encoded = encode(&data, chunk).await.unwrap();
+                assert_eq!(
+                    String::from_utf8(encoded.clone()).unwrap(),
+                    expected,
+                    "{len} bytes in chunks of {chunk}"
+                );
+                assert_eq!(encoded.len() as u64, encoded_len(len as u64) + 2);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_content_of_the_wrong_length() {
+        let content = || stream::iter([Ok::<_, io::Error>(vec![0; 4])]);
+
+        let longer = base64_stream(content(), 3, "").try_concat().await;
+        assert_eq!(longer.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let shorter = base64_stream(content(), 5, "").try_concat().await;
+        assert_eq!(shorter.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn refuses_files_over_the_limit() {
+        let airtable = Airtable::new("key", "appTest", "").unwrap();
+        let e = airtable
+            .upload_attachment(
+                "recTest",
+                "images",
+                "big.jpg",
+                "image/jpeg",
+                MAX_UPLOAD_SIZE + 1,
+                stream::iter([Ok::<_, io::Error>(Vec::<u8>::new())]),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            e,
+            AirtableError::InvalidRequest {
+                status: StatusCode::PAYLOAD_TOO_LARGE,
+                ..
+            }
+        ));
+    }
+}
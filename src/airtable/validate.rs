@@ -0,0 +1,546 @@
+//! Check record types against the live schema of a base.
+//!
+//! A renamed Airtable field makes the matching `Option` field of a record type
+//! silently deserialize to `None`. Registering the record types with a
+//! [`SchemaValidator`] compares their serde field names and types, taken from
+//! their [`JsonSchema`], with the tables in the base and reports every field
+//! that is missing, looks renamed or has an incompatible type.
+//!
+//! ```ignore
+//! let reports = SchemaValidator::new()
+//!     .register::<Item>("items")
+//!     .check(&airtable)
+//!     .await?;
+//!
+//! for report in reports.iter().filter(|r| !r.is_ok()) {
+//!     log::error!("{report}");
+//! }
+//! ```
+use std::fmt;
+
+use schemars::{
+    gen::SchemaSettings,
+    schema::{InstanceType, Schema, SingleOrVec},
+    JsonSchema, Map,
+};
+
+use super::{Airtable, FieldOptions, FieldType, Result, TableSchema};
+
+/// The JSON type of a record field, as described by its `JsonSchema`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonKind {
+    /// Any value, e.g. `serde_json::Value`.
+    Any,
+    String,
+    Integer,
+    Number,
+    Boolean,
+    Array,
+    Object,
+}
+
+impl fmt::Display for JsonKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JsonKind::Any => "any",
+            JsonKind::String => "string",
+            JsonKind::Integer => "integer",
+            JsonKind::Number => "number",
+            JsonKind::Boolean => "boolean",
+            JsonKind::Array => "array",
+            JsonKind::Object => "object",
+        })
+    }
+}
+
+/// A difference between a record type and its table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaIssue {
+    /// The table does not exist in the base.
+    MissingTable,
+    /// The record type has a field the table does not have. `renamed_to` is a
+    /// field of the table with a similar name that the record type does not use.
+    MissingField {
+        field: String,
+        renamed_to: Option<String>,
+    },
+    /// The field exists but holds values the record type cannot deserialize.
+    IncompatibleType {
+        field: String,
+        expected: JsonKind,
+        actual: FieldType,
+    },
+}
+
+impl fmt::Display for SchemaIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaIssue::MissingTable => f.write_str("table is missing"),
+            SchemaIssue::MissingField {
+                field,
+                renamed_to: Some(renamed_to),
+            } => write!(
+                f,
+                "field `{field}` is missing, was it renamed to `{renamed_to}`?"
+            ),
+            SchemaIssue::MissingField { field, .. } => write!(f, "field `{field}` is missing"),
+            SchemaIssue::IncompatibleType {
+                field,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "field `{field}` is a {actual:?} field but the record type expects {expected} values"
+            ),
+        }
+    }
+}
+
+/// The result of checking one record type against its table.
+#[derive(Debug, Clone)]
+pub struct SchemaReport {
+    pub table: String,
+    pub type_name: String,
+    pub issues: Vec<SchemaIssue>,
+}
+
+impl SchemaReport {
+    /// Returns true if the record type matches the table.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for SchemaReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.table, self.type_name)?;
+        if self.issues.is_empty() {
+            return f.write_str(": ok");
+        }
+        for issue in &self.issues {
+            write!(f, "\n  - {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A record type registered with a [`SchemaValidator`].
+struct Registration {
+    table: String,
+    type_name: String,
+    fields: Vec<(String, JsonKind)>,
+}
+
+/// Checks registered record types against the tables of a base.
+#[derive(Default)]
+pub struct SchemaValidator {
+    registrations: Vec<Registration>,
+}
+
+impl SchemaValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the record type `T` for `table`.
+    pub fn register<T: JsonSchema>(mut self, table: &str) -> Self {
+        self.registrations.push(Registration {
+            table: table.to_string(),
+            type_name: T::schema_name(),
+            fields: record_fields::<T>(),
+        });
+        self
+    }
+
+    /// Fetch the schema of the base and check every registered record type.
+    pub async fn check(&self, airtable: &Airtable) -> Result<Vec<SchemaReport>> {
+        let tables = airtable.list_tables().await?;
+        Ok(self.check_tables(&tables))
+    }
+
+    /// Check every registered record type against already fetched tables.
+    pub fn check_tables(&self, tables: &[TableSchema]) -> Vec<SchemaReport> {
+        self.registrations
+            .iter()
+            .map(|r| {
+                let issues = match tables.iter().find(|t| t.name == r.table || t.id == r.table) {
+                    Some(table) => compare(&r.fields, table),
+                    None => vec![SchemaIssue::MissingTable],
+                };
+
+                SchemaReport {
+                    table: r.table.to_string(),
+                    type_name: r.type_name.to_string(),
+                    issues,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The serde names and JSON types of the fields of `T`.
+pub fn record_fields<T: JsonSchema>() -> Vec<(String, JsonKind)> {
+    // Describe `Option<T>` the same as `T`, every field is optional in Airtable.
+    let root = SchemaSettings::draft07()
+        .with(|s| s.option_add_null_type = false)
+        .into_generator()
+        .into_root_schema_for::<T>();
+
+    match &root.schema.object {
+        Some(object) => object
+            .properties
+            .iter()
+            .map(|(name, schema)| (name.to_string(), json_kind(schema, &root.definitions, 0)))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+fn json_kind(schema: &Schema, definitions: &Map<String, Schema>, depth: usize) -> JsonKind {
+    let object = match schema {
+        Schema::Bool(_) => return JsonKind::Any,
+        Schema::Object(object) => object,
+    };
+
+    if let Some(reference) = &object.reference {
+        let name = reference.trim_start_matches("#/definitions/");
+        return match definitions.get(name) {
+            Some(schema) if depth < 8 => json_kind(schema, definitions, depth + 1),
+            _ => JsonKind::Object,
+        };
+    }
+
+    let instance_types: Vec<InstanceType> = match &object.instance_type {
+        Some(SingleOrVec::Single(t)) => vec![**t],
+        Some(SingleOrVec::Vec(ts)) => ts.clone(),
+        None => Vec::new(),
+    };
+    if let Some(t) = instance_types.iter().find(|t| **t != InstanceType::Null) {
+        return match t {
+            InstanceType::String => JsonKind::String,
+            InstanceType::Integer => JsonKind::Integer,
+            InstanceType::Number => JsonKind::Number,
+            InstanceType::Boolean => JsonKind::Boolean,
+            InstanceType::Array => JsonKind::Array,
+            InstanceType::Object => JsonKind::Object,
+            InstanceType::Null => JsonKind::Any,
+        };
+    }
+
+    // Enums and `Option`s of structs are a list of alternatives.
+    object
+        .subschemas
+        .as_ref()
+        .and_then(|s| s.any_of.as_ref().or(s.one_of.as_ref()))
+        .and_then(|alternatives| {
+            alternatives
+                .iter()
+                .map(|s| json_kind(s, definitions, depth + 1))
+                .find(|k| *k != JsonKind::Any)
+        })
+        .unwrap_or(JsonKind::Any)
+}
+
+/// Compare the fields of a record type with a table.
+fn compare(fields: &[(String, JsonKind)], table: &TableSchema) -> Vec<SchemaIssue> {
+    let mut issues = Vec::new();
+
+    for (name, kind) in fields {
+        match table.fields.iter().find(|f| &f.name == name) {
+            Some(field) if !accepts(field.type_, field.options.as_ref(), *kind) => {
+                issues.push(SchemaIssue::IncompatibleType {
+                    field: name.to_string(),
+                    expected: *kind,
+                    actual: field.type_,
+                });
+            }
+            Some(_) => (),
+            None => {
+                // Only suggest fields that no other record field already uses.
+                let renamed_to = table
+                    .fields
+                    .iter()
+                    .filter(|f| !fields.iter().any(|(n, _)| n == &f.name))
+                    .filter(|f| accepts(f.type_, f.options.as_ref(), *kind))
+                    .filter(|f| similar(name, &f.name))
+                    .map(|f| f.name.to_string())
+                    .next();
+
+                issues.push(SchemaIssue::MissingField {
+                    field: name.to_string(),
+                    renamed_to,
+                });
+            }
+        }
+    }
+
+    issues
+}
+
+/// Whether a record field of JSON type `kind` can hold the values of a field.
+fn accepts(type_: FieldType, options: Option<&FieldOptions>, kind: JsonKind) -> bool {
+    if kind == JsonKind::Any {
+        return true;
+    }
+
+    let precision = options.and_then(|o| o.precision);
+    let result = options.and_then(|o| o.result.as_deref());
+
+    match type_ {
+        FieldType::SingleLineText
+        | FieldType::Email
+        | FieldType::Url
+        | FieldType::MultilineText
+        | FieldType::PhoneNumber
+        | FieldType::RichText
+        | FieldType::SingleSelect
+        | FieldType::Date
+        | FieldType::DateTime
+        | FieldType::CreatedTime
+        | FieldType::LastModifiedTime => kind == JsonKind::String,
+        // Whole numbers fit an integer, anything with decimals needs a float.
+        FieldType::Number | FieldType::Duration if precision == Some(0) => {
+            matches!(kind, JsonKind::Integer | JsonKind::Number)
+        }
+        FieldType::Number | FieldType::Percent | FieldType::Currency | FieldType::Duration => {
+            kind == JsonKind::Number
+        }
+        FieldType::Rating | FieldType::Count | FieldType::AutoNumber => {
+            matches!(kind, JsonKind::Integer | JsonKind::Number)
+        }
+        FieldType::Checkbox => kind == JsonKind::Boolean,
+        FieldType::MultipleSelects
+        | FieldType::MultipleRecordLinks
+        | FieldType::MultipleAttachments
+        | FieldType::MultipleCollaborators
+        | FieldType::Lookup
+        | FieldType::MultipleLookupValues => kind == JsonKind::Array,
+        FieldType::SingleCollaborator
+        | FieldType::CreatedBy
+        | FieldType::LastModifiedBy
+        | FieldType::Barcode
+        | FieldType::Button
+        | FieldType::AiText => kind == JsonKind::Object,
+        FieldType::Formula | FieldType::Rollup => match result {
+            Some(result) => accepts(result.type_, result.options.as_ref(), kind),
+            None => true,
+        },
+        FieldType::ExternalSyncSource | FieldType::Unknown => true,
+    }
+}
+
+/// Whether two field names look like one was renamed to the other.
+fn similar(a: &str, b: &str) -> bool {
+    let normalize = |s: &str| -> Vec<char> {
+        s.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let (a, b) = (normalize(a), normalize(b));
+
+    if a.is_empty() || b.is_empty() {
+        return false;
+    }
+    if a == b || a.starts_with(&b[..]) || b.starts_with(&a[..]) {
+        return true;
+    }
+
+    edit_distance(&a, &b) <= 2.max(a.len().min(b.len()) / 4)
+}
+
+/// The Levenshtein distance between two strings.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn table() -> TableSchema {
+        serde_json::from_value(json!({
+            "id": "tblItems",
+            "name": "items",
+            "primaryFieldId": "fldName",
+            "fields": [
+                { "id": "fldName", "name": "Name", "type": "singleLineText" },
+                { "id": "fldPrice", "name": "price", "type": "currency", "options": { "precision": 2 } },
+                { "id": "fldCount", "name": "count", "type": "number", "options": { "precision": 0 } },
+                { "id": "fldDone", "name": "done", "type": "checkbox" },
+                { "id": "fldImages", "name": "Image", "type": "multipleAttachments" },
+                { "id": "fldTotal", "name": "total", "type": "formula",
+                  "options": { "result": { "type": "number", "options": { "precision": 1 } } } }
+            ]
+        }))
+        .unwrap()
+    }
+
+    /// The fields of a record type and the issues comparing them finds.
+    type Case = (&'static [(&'static str, JsonKind)], Vec<SchemaIssue>);
+
+    fn fields(fields: &[(&str, JsonKind)]) -> Vec<(String, JsonKind)> {
+        fields.iter().map(|(n, k)| (n.to_string(), *k)).collect()
+    }
+
+    #[test]
+    fn compares_record_fields_with_the_table() {
+        use JsonKind::*;
+
+        let missing = |field: &str, renamed_to: Option<&str>| SchemaIssue::MissingField {
+            field: field.to_string(),
+            renamed_to: renamed_to.map(|r| r.to_string()),
+        };
+        let incompatible = |field: &str, expected, actual| SchemaIssue::IncompatibleType {
+            field: field.to_string(),
+            expected,
+            actual,
+        };
+
+        let cases: &[Case] = &[
+            (
+                &[("Name", String), ("price", Number), ("done", Boolean)],
+                vec![],
+            ),
+            (
+                &[("count", Integer), ("total", Number), ("done", Any)],
+                vec![],
+            ),
+            (
+                &[("description", String)],
+                vec![missing("description", None)],
+            ),
+            // Renamed, only to fields of a type that fits and not used already.
+            (&[("name", String)], vec![missing("name", Some("Name"))]),
+            (&[("images", Array)], vec![missing("images", Some("Image"))]),
+            (&[("images", String)], vec![missing("images", None)]),
+            (
+                &[("Name", String), ("nme", String)],
+                vec![missing("nme", None)],
+            ),
+            (
+                &[("price", Integer)],
+                vec![incompatible("price", Integer, FieldType::Currency)],
+            ),
+            (
+                &[("total", String), ("done", String)],
+                vec![
+                    incompatible("total", String, FieldType::Formula),
+                    incompatible("done", String, FieldType::Checkbox),
+                ],
+            ),
+        ];
+
+        let table = table();
+        for (record, issues) in cases {
+            assert_eq!(&compare(&fields(record), &table), issues, "{record:?}");
+        }
+    }
+
+    #[test]
+    fn accepts_compatible_kinds() {
+        use JsonKind::*;
+
+        let options =
+            |o: serde_json::Value| Some(serde_json::from_value::<FieldOptions>(o).unwrap());
+        let cases = [
+            (FieldType::SingleLineText, None, String, true),
+            (FieldType::SingleLineText, None, Number, false),
+            (
+                FieldType::Number,
+                options(json!({ "precision": 0 })),
+                Integer,
+                true,
+            ),
+            (
+                FieldType::Number,
+                options(json!({ "precision": 2 })),
+                Integer,
+                false,
+            ),
+            (FieldType::Number, None, Number, true),
+            (FieldType::Rating, None, Integer, true),
+            (FieldType::Checkbox, None, Boolean, true),
+            (FieldType::MultipleRecordLinks, None, Array, true),
+            (FieldType::Barcode, None, Object, true),
+            (FieldType::Barcode, None, String, false),
+            (
+                FieldType::Rollup,
+                options(json!({ "result": { "type": "singleLineText" } })),
+                String,
+                true,
+            ),
+            (
+                FieldType::Rollup,
+                options(json!({ "result": { "type": "singleLineText" } })),
+                Number,
+                false,
+            ),
+            (FieldType::Formula, None, Number, true),
+            (FieldType::Unknown, None, Boolean, true),
+            (FieldType::Date, None, Any, true),
+        ];
+
+        for (type_, options, kind, accepted) in cases {
+            assert_eq!(
+                accepts(type_, options.as_ref(), kind),
+                accepted,
+                "{type_:?} {kind}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_unknown_tables() {
+        #[derive(JsonSchema)]
+        #[allow(dead_code)]
+        struct Item {
+            #[serde(rename = "Name")]
+            name: String,
+            price: Option<f64>,
+        }
+
+        let reports = SchemaValidator::new()
+            .register::<Item>("items")
+            .register::<Item>("tblItems")
+            .register::<Item>("wishes")
+            .check_tables(&[table()]);
+
+        let issues: Vec<_> = reports.iter().map(|r| r.issues.clone()).collect();
+        assert_eq!(issues, [vec![], vec![], vec![SchemaIssue::MissingTable]]);
+        assert_eq!(
+            reports[2].to_string(),
+            "wishes (Item)\n  - table is missing"
+        );
+    }
+
+    #[test]
+    fn measures_edit_distance() {
+        let distance = |a: &str, b: &str| {
+            let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+            edit_distance(&a, &b)
+        };
+        assert_eq!(distance("", ""), 0);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("price", "price"), 0);
+        assert_eq!(distance("price", "prices"), 1);
+        assert_eq!(distance("kitten", "sitting"), 3);
+
+        assert!(similar("Product Name", "product_name"));
+        assert!(similar("image", "images"));
+        assert!(similar("catgory", "category"));
+        assert!(!similar("price", "name"));
+        assert!(!similar("", "name"));
+    }
+}
@@ -0,0 +1,389 @@
+//! The Airtable webhooks API, notifying us when records in the base change.
+//!
+//! Airtable only pings the notification URL with the id of the webhook, the
+//! changes themselves are fetched from the payloads endpoint, cursor by cursor.
+//!
+//! FROM: https://airtable.com/developers/web/api/webhooks-overview
+use std::collections::HashMap;
+
+use base64::Engine;
+use chrono::{offset::Utc, DateTime};
+use hmac::{Hmac, Mac};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::{error, Airtable, AirtableError, Result};
+
+/// The header Airtable signs notifications with.
+pub const MAC_HEADER: &str = "X-Airtable-Content-MAC";
+
+impl Airtable {
+    /// Create a webhook for the base. The MAC secret of the webhook is only
+    /// returned here, keep it to verify the notifications.
+    /// FROM: https://airtable.com/developers/web/api/create-a-webhook
+    pub async fn create_webhook(&self, webhook: &NewWebhook) -> Result<CreatedWebhook> {
+        // Build the request.
+        let request = self.request_to(
+            Method::POST,
+            format!("bases/{}/webhooks", self.base_id),
+            webhook,
+            None,
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: CreatedWebhook = error::json(resp).await?;
+
+        Ok(r)
+    }
+
+    /// List the webhooks of the base.
+    /// FROM: https://airtable.com/developers/web/api/list-webhooks
+    pub async fn list_webhooks(&self) -> Result<Vec<Webhook>> {
+        // Build the request.
+        let request = self.request_to(
+            Method::GET,
+            format!("bases/{}/webhooks", self.base_id),
+            (),
+            None,
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: WebhooksResponse = error::json(resp).await?;
+
+        Ok(r.webhooks)
+    }
+
+    /// Extend the life of a webhook, webhooks expire 7 days after they were
+    /// created or last refreshed. Returns the new expiration time.
+    /// FROM: https://airtable.com/developers/web/api/refresh-a-webhook
+    pub async fn refresh_webhook(&self, webhook_id: &str) -> Result<Option<DateTime<Utc>>> {
+        // Build the request.
+        let request = self.request_to(
+            Method::POST,
+            format!("bases/{}/webhooks/{webhook_id}/refresh", self.base_id),
+            serde_json::Map::new(),
+            None,
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: RefreshResponse = error::json(resp).await?;
+
+        Ok(r.expiration_time)
+    }
+
+    /// Delete a webhook.
+    /// FROM: https://airtable.com/developers/web/api/delete-a-webhook
+    pub async fn delete_webhook(&self, webhook_id: &str) -> Result<()> {
+        // Build the request.
+        let request = self.request_to(
+            Method::DELETE,
+            format!("bases/{}/webhooks/{webhook_id}", self.base_id),
+            (),
+            None,
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK | StatusCode::NO_CONTENT => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        Ok(())
+    }
+
+    /// List the payloads of a webhook from `cursor` on. Keep calling with the
+    /// returned cursor while `might_have_more` is set.
+    /// FROM: https://airtable.com/developers/web/api/list-webhook-payloads
+    pub async fn list_webhook_payloads(
+        &self,
+        webhook_id: &str,
+        cursor: u64,
+    ) -> Result<WebhookPayloads> {
+        // Build the request.
+        let request = self.request_to(
+            Method::GET,
+            format!("bases/{}/webhooks/{webhook_id}/payloads", self.base_id),
+            (),
+            Some(vec![("cursor", cursor.to_string())]),
+        )?;
+
+        let resp = self.client.execute(request).await?;
+        match resp.status() {
+            StatusCode::OK => (),
+            _ => return Err(AirtableError::from_response(resp).await),
+        };
+
+        // Try to deserialize the response.
+        let r: WebhookPayloads = error::json(resp).await?;
+
+        Ok(r)
+    }
+}
+
+/// Check the MAC Airtable sent in the [`MAC_HEADER`] of a notification against
+/// the body of the notification, with the secret returned when the webhook was
+/// created.
+pub fn verify_mac(mac_secret_base64: &str, body: &[u8], header: &str) -> bool {
+    let Ok(secret) = base64::engine::general_purpose::STANDARD.decode(mac_secret_base64) else {
+        return false;
+    };
+    let Some(Ok(expected)) = header.strip_prefix("hmac-sha256=").map(hex::decode) else {
+        return false;
+    };
+
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(&secret) else {
+        return false;
+    };
+    mac.update(body);
+
+    // Compares in constant time.
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// A webhook to create.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewWebhook {
+    /// Where Airtable sends the notifications. Without it the payloads can
+    /// still be listed.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "notificationUrl"
+    )]
+    pub notification_url: Option<String>,
+    pub specification: WebhookSpecification,
+}
+
+impl NewWebhook {
+    /// A webhook for changes to the records of `table`, or of every table in
+    /// the base when `table` is `None`.
+    pub fn records<S: ToString>(notification_url: S, table: Option<&str>) -> Self {
+        Self {
+            notification_url: Some(notification_url.to_string()),
+            specification: WebhookSpecification {
+                options: WebhookOptions {
+                    filters: WebhookFilters {
+                        data_types: vec!["tableData".to_string()],
+                        record_change_scope: table.map(|t| t.to_string()),
+                        other: Default::default(),
+                    },
+                },
+            },
+        }
+    }
+}
+
+/// What a webhook is notified about.
+/// FROM: https://airtable.com/developers/web/api/model/webhooks-specification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookSpecification {
+    pub options: WebhookOptions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookOptions {
+    pub filters: WebhookFilters,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookFilters {
+    /// Any of `tableData`, `tableFields` and `tableMetadata`.
+    #[serde(rename = "dataTypes")]
+    pub data_types: Vec<String>,
+    /// Only notify about changes in this table.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "recordChangeScope"
+    )]
+    pub record_change_scope: Option<String>,
+    /// The other filters, e.g. `watchDataInFieldIds`.
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A webhook that was just created.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatedWebhook {
+    pub id: String,
+    /// The secret notifications are signed with, see [`verify_mac`].
+    #[serde(rename = "macSecretBase64")]
+    pub mac_secret_base64: String,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "expirationTime"
+    )]
+    pub expiration_time: Option<DateTime<Utc>>,
+}
+
+/// A webhook of the base.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: String,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "notificationUrl"
+    )]
+    pub notification_url: Option<String>,
+    #[serde(default, rename = "areNotificationsEnabled")]
+    pub are_notifications_enabled: bool,
+    #[serde(default, rename = "isHookEnabled")]
+    pub is_hook_enabled: bool,
+    /// The cursor of the next payload, i.e. one past the latest change.
+    #[serde(rename = "cursorForNextPayload")]
+    pub cursor_for_next_payload: u64,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "expirationTime"
+    )]
+    pub expiration_time: Option<DateTime<Utc>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "lastSuccessfulNotificationTime"
+    )]
+    pub last_successful_notification_time: Option<DateTime<Utc>>,
+    pub specification: WebhookSpecification,
+}
+
+/// The ping Airtable sends to the notification URL of a webhook.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookNotification {
+    pub base: IdRef,
+    pub webhook: IdRef,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdRef {
+    pub id: String,
+}
+
+/// A page of webhook payloads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayloads {
+    /// The cursor to list the next payloads from.
+    pub cursor: u64,
+    #[serde(rename = "mightHaveMore")]
+    pub might_have_more: bool,
+    pub payloads: Vec<WebhookPayload>,
+}
+
+/// The changes made in one transaction.
+/// FROM: https://airtable.com/developers/web/api/model/webhooks-payload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub timestamp: DateTime<Utc>,
+    #[serde(rename = "baseTransactionNumber")]
+    pub base_transaction_number: u64,
+    #[serde(default, rename = "payloadFormat")]
+    pub payload_format: String,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "actionMetadata"
+    )]
+    pub action_metadata: Option<serde_json::Value>,
+    /// The changes by table id.
+    #[serde(default, rename = "changedTablesById")]
+    pub changed_tables_by_id: HashMap<String, TableChanges>,
+}
+
+/// The changes made to the records of a table. The cell values are keyed by
+/// field id.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TableChanges {
+    #[serde(default, rename = "createdRecordsById")]
+    pub created_records_by_id: HashMap<String, serde_json::Value>,
+    #[serde(default, rename = "changedRecordsById")]
+    pub changed_records_by_id: HashMap<String, serde_json::Value>,
+    #[serde(default, rename = "destroyedRecordIds")]
+    pub destroyed_record_ids: Vec<String>,
+}
+
+impl TableChanges {
+    /// The ids of the created, changed and destroyed records.
+    pub fn record_ids(&self) -> impl Iterator<Item = &str> {
+        self.created_records_by_id
+            .keys()
+            .chain(self.changed_records_by_id.keys())
+            .chain(self.destroyed_record_ids.iter())
+            .map(|id| id.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct WebhooksResponse {
+    webhooks: Vec<Webhook>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RefreshResponse {
+    #[serde(default, rename = "expirationTime")]
+    expiration_time: Option<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "dW5yZWdpc3RyeS13ZWJob29rLXNlY3JldA==";
+    const BODY: &[u8] = br#"{"base":{"id":"appFakeUnregistry"},"webhook":{"id":"achFakeWebhook"},"timestamp":"2023-10-01T12:00:00.000Z"}"#;
+    const MAC: &str =
+        "hmac-sha256=e8adc65a84bb086c70b72ae963d5d435fe1b464bb086a9dcc6999ef97c665105";
+
+    #[test]
+    fn verifies_the_mac() {
+        assert!(verify_mac(SECRET, BODY, MAC));
+    }
+
+    #[test]
+    fn rejects_a_tampered_mac() {
+        let tampered = MAC.replace("e8ad", "e8ae");
+        assert!(!verify_mac(SECRET, BODY, &tampered));
+
+        // Or a tampered body.
+        let body = String::from_utf8_lossy(BODY).replace("achFakeWebhook", "achOtherWebhook");
+        assert!(!verify_mac(SECRET, body.as_bytes(), MAC));
+    }
+
+    #[test]
+    fn rejects_a_mac_without_prefix() {
+        let unprefixed = MAC.trim_start_matches("hmac-sha256=");
+        assert!(!verify_mac(SECRET, BODY, unprefixed));
+        assert!(!verify_mac(SECRET, BODY, ""));
+    }
+
+    #[test]
+    fn rejects_an_invalid_secret() {
+        assert!(!verify_mac("not base64!", BODY, MAC));
+    }
+
+    #[test]
+    fn parses_notifications() {
+        let notification: WebhookNotification = serde_json::from_slice(BODY).unwrap();
+        assert_eq!(notification.webhook.id, "achFakeWebhook");
+    }
+}
@@ -1,25 +1,50 @@
-use crate::airtable::*;
+use crate::airtable::{self, *};
 use leptos::*;
-use leptos_meta::*;
+// Both export a `Link`, the one in views is the `<link>` element.
+use leptos_meta::{Link, *};
 use leptos_router::*;
 use log::info;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The Airtable table the items for sale are kept in.
+pub const ITEMS_TABLE: &str = "items";
+/// The view of [`ITEMS_TABLE`] the items are listed from.
+pub const ITEMS_VIEW: &str = "Grid view";
+/// The Airtable table the item categories are kept in.
+pub const CATEGORIES_TABLE: &str = "categories";
+/// The Airtable table the claims on items are kept in.
+pub const CLAIMS_TABLE: &str = "claims";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Item {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub price: Option<i32>,
+    pub price: Option<Currency>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub images: Option<Vec<Attachment>>,
+    #[serde(default)]
+    pub categories: airtable::Link<Category>,
+    #[serde(default)]
+    pub publish: Checkbox,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Category {
+    pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub categories: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub publish: Option<bool>,
+    pub description: Option<String>,
+    #[serde(
+        rename = "sort order",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sort_order: Option<i64>,
 }
 
 // #[derive(Clone, Debug, Default)]
@@ -42,36 +67,308 @@ pub struct Item {
 
 #[server(LoadData, "/api", "GetJson")]
 pub async fn load_data() -> Result<Vec<Record<Item>>, ServerFnError> {
+    // Serve the list we have while the webhook reports no changes.
+    let item_cache = use_context::<ItemCache>();
+    if let Some(items) = item_cache.as_ref().and_then(|c| c.items()) {
+        return Ok(items);
+    }
+    let generation = item_cache.as_ref().map(|c| c.generation());
+
     // Initialize the Airtable client.
-    let airtable = Airtable::new_from_env();
+    let airtable = Airtable::new_from_env().map_err(server_error)?;
 
     // Get the current records from a table.
-    match airtable.list_records::<Item>("items", "Grid view").await {
-        Ok(records) => Ok(records),
-        Err(e) => Err(ServerFnError::ServerError(e.to_string())),
+    match items_table(&airtable).list_with(&items_options()).await {
+        Ok(mut records) => {
+            // Serve the images from our own copies, the Airtable urls expire.
+            if let Some(cache) = use_context::<crate::media::AttachmentCache>() {
+                for record in &mut records {
+                    for image in record.fields.images.iter_mut().flatten() {
+                        cache.register(image);
+                    }
+                }
+            }
+            if let (Some(cache), Some(generation)) = (&item_cache, generation) {
+                cache.set(generation, records.clone());
+            }
+            Ok(records)
+        }
+        Err(e) => Err(server_error(e)),
+    }
+}
+
+/// The categories of the published items, in sort order.
+#[server(LoadCategories, "/api", "GetJson")]
+pub async fn load_categories() -> Result<Vec<Record<Category>>, ServerFnError> {
+    // Initialize the Airtable client.
+    let airtable = Airtable::new_from_env().map_err(server_error)?;
+
+    let options = ListOptions {
+        fields: vec!["categories".to_string()],
+        filter_by_formula: Some(Formula::field("publish").equals(true)),
+        lenient: true,
+        ..Default::default()
+    };
+    let items = items_table(&airtable)
+        .list_with(&options)
+        .await
+        .map_err(server_error)?;
+
+    // Fetch the linked categories in as few requests as possible.
+    match airtable
+        .table::<Category>(CATEGORIES_TABLE)
+        .resolve(&items, |i| &i.categories)
+        .await
+    {
+        Ok(categories) => Ok(get_categories(&items, &categories)),
+        Err(e) => Err(server_error(e)),
     }
 }
 
-pub fn get_categories(items: Vec<Record<Item>>) -> Vec<String> {
-    let mut categories: Vec<String> = items
+/// The questions and answers about an item, kept as comments on its record,
+/// oldest first.
+#[server(GetItemComments, "/api", "GetJson")]
+pub async fn get_item_comments(record_id: String) -> Result<Vec<Comment>, ServerFnError> {
+    // Initialize the Airtable client.
+    let airtable = Airtable::new_from_env().map_err(server_error)?;
+
+    match items_table(&airtable).comments(&record_id).await {
+        Ok(mut comments) => {
+            // Sort them rather than rely on the order they are listed in.
+            comments.sort_by_key(|c| c.created_time);
+            Ok(comments)
+        }
+        Err(e) => Err(item_error(e)),
+    }
+}
+
+/// The items for sale, listed from [`ITEMS_VIEW`].
+#[cfg(feature = "ssr")]
+fn items_table(airtable: &Airtable) -> Table<'_, Item> {
+    airtable.table(ITEMS_TABLE).view(ITEMS_VIEW)
+}
+
+/// Only the published items and the fields we render, in name order.
+#[cfg(feature = "ssr")]
+fn items_options() -> ListOptions {
+    ListOptions {
+        fields: [
+            "name",
+            "description",
+            "price",
+            "images",
+            "categories",
+            "publish",
+        ]
         .into_iter()
-        .map(|i| i.fields.categories)
-        .filter(|c| c.is_some())
-        .map(|c| c.unwrap())
-        .flatten()
+        .map(String::from)
+        .collect(),
+        filter_by_formula: Some(Formula::field("publish").equals(true)),
+        sort: vec![Sort::asc("name")],
+        // One bad cell shouldn't take the whole list down.
+        lenient: true,
+        ..Default::default()
+    }
+}
+
+/// The items listed by [`load_data`], kept until the webhook reports a change
+/// in the base. Only provide it when a webhook is configured, without one the
+/// list never gets refreshed.
+#[cfg(feature = "ssr")]
+#[derive(Clone, Default)]
+pub struct ItemCache {
+    inner: std::sync::Arc<std::sync::RwLock<CachedItems>>,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Default)]
+struct CachedItems {
+    /// Bumped on every change, so a list fetched before a change isn't kept.
+    generation: u64,
+    items: Option<Vec<Record<Item>>>,
+}
+
+#[cfg(feature = "ssr")]
+impl ItemCache {
+    /// The cached items, if nothing changed since they were listed.
+    pub fn items(&self) -> Option<Vec<Record<Item>>> {
+        self.read().items.clone()
+    }
+
+    /// The generation to pass to [`ItemCache::set`] with the items listed
+    /// from now on.
+    pub fn generation(&self) -> u64 {
+        self.read().generation
+    }
+
+    /// Keep `items` unless the base changed since `generation`.
+    pub fn set(&self, generation: u64, items: Vec<Record<Item>>) {
+        let mut cached = self.write();
+        if cached.generation == generation {
+            cached.items = Some(items);
+        }
+    }
+
+    /// Drop the cached items.
+    pub fn clear(&self) {
+        let mut cached = self.write();
+        cached.generation += 1;
+        cached.items = None;
+    }
+
+    /// Clear the cache on every change the webhook passes on. Changes in any
+    /// table clear it, the webhook names the tables by id.
+    pub fn clear_on_changes(
+        &self,
+        mut changes: tokio::sync::broadcast::Receiver<crate::webhooks::RecordChanges>,
+    ) {
+        use tokio::sync::broadcast::error::RecvError;
+
+        let cache = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match changes.recv().await {
+                    // Missed changes still mean the items may be stale.
+                    Ok(_) | Err(RecvError::Lagged(_)) => cache.clear(),
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, CachedItems> {
+        self.inner.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, CachedItems> {
+        self.inner.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Check that the base has the table and view that [`load_data`] relies on, and
+/// compare the record types with their tables.
+#[cfg(feature = "ssr")]
+pub async fn check_airtable_schema(
+    airtable: &Airtable,
+) -> Result<Vec<SchemaReport>, AirtableError> {
+    let tables = airtable.list_tables().await?;
+
+    if let Some(items) = tables.iter().find(|t| t.name == ITEMS_TABLE) {
+        if items.view(ITEMS_VIEW).is_none() {
+            return Err(AirtableError::NotFound(ApiError {
+                type_: "VIEW_NOT_FOUND".to_string(),
+                message: format!("table {ITEMS_TABLE} has no view named {ITEMS_VIEW}"),
+            }));
+        }
+    }
+
+    Ok(SchemaValidator::new()
+        .register::<Item>(ITEMS_TABLE)
+        .register::<Category>(CATEGORIES_TABLE)
+        .check_tables(&tables))
+}
+
+/// The tables the registry expects, for [`Airtable::bootstrap`].
+#[cfg(feature = "ssr")]
+pub fn registry_tables() -> Vec<NewTable> {
+    vec![
+        NewTable::new(
+            CATEGORIES_TABLE,
+            vec![
+                NewField::new("name", FieldType::SingleLineText),
+                NewField::new("description", FieldType::MultilineText),
+                NewField::number("sort order", 0)
+                    .description("Categories are listed from the lowest to the highest."),
+            ],
+        )
+        .description("The categories items are grouped in."),
+        NewTable::new(
+            ITEMS_TABLE,
+            vec![
+                NewField::new("name", FieldType::SingleLineText),
+                NewField::new("description", FieldType::MultilineText),
+                NewField::currency("price", "$", 2),
+                NewField::new("images", FieldType::MultipleAttachments),
+                NewField::link("categories", CATEGORIES_TABLE),
+                NewField::checkbox("publish").description("Only published items are listed."),
+            ],
+        )
+        .description("The items for sale."),
+        NewTable::new(
+            CLAIMS_TABLE,
+            vec![
+                NewField::new("name", FieldType::SingleLineText),
+                NewField::new("email", FieldType::Email),
+                NewField::link("item", ITEMS_TABLE),
+                NewField::new("message", FieldType::MultilineText),
+            ],
+        )
+        .description("The items people have claimed."),
+    ]
+}
+
+/// Turn an Airtable error into a message that can be shown to visitors.
+#[cfg(feature = "ssr")]
+fn server_error(e: AirtableError) -> ServerFnError {
+    log::error!("airtable request failed: {e}");
+
+    let message = match e {
+        AirtableError::RateLimited { .. } => {
+            "Too many people are looking right now, try again soon."
+        }
+        // A missing table or view is a configuration error, not a gone item.
+        AirtableError::NotFound(_)
+        | AirtableError::Unauthorized { .. }
+        | AirtableError::Config(_)
+        | AirtableError::MissingEnterpriseAccount => "The registry is not set up correctly.",
+        _ => "Something went wrong loading the registry.",
+    };
+
+    ServerFnError::ServerError(message.to_string())
+}
+
+/// Like [`server_error`], for requests about a single item, where a missing
+/// record means the item was removed.
+#[cfg(feature = "ssr")]
+fn item_error(e: AirtableError) -> ServerFnError {
+    if e.is_missing_record() {
+        log::warn!("airtable record not found: {e}");
+        return ServerFnError::ServerError("This item no longer exists.".to_string());
+    }
+
+    server_error(e)
+}
+
+/// The categories linked from `items`, ordered by their sort order and then
+/// by name.
+pub fn get_categories(
+    items: &[Record<Item>],
+    categories: &HashMap<String, Record<Category>>,
+) -> Vec<Record<Category>> {
+    let mut linked: Vec<Record<Category>> = items
+        .iter()
+        .flat_map(|i| i.fields.categories.resolve(categories))
+        .cloned()
         .collect();
 
-    categories.sort();
-    categories.dedup();
+    // Categories without a sort order go last.
+    linked.sort_by_key(|c| {
+        (
+            c.fields.sort_order.unwrap_or(i64::MAX),
+            c.fields.name.clone(),
+            c.id.clone(),
+        )
+    });
+    linked.dedup_by(|a, b| a.id == b.id);
 
-    categories
+    linked
 }
 
 fn get_items_by_category(items: Vec<Record<Item>>, category: Option<String>) -> Vec<Record<Item>> {
     let published = items
         .clone()
         .into_iter()
-        .filter(|i| i.fields.publish.is_some())
+        .filter(|i| i.fields.publish.is_checked())
         .collect();
 
     match category {
@@ -79,15 +376,7 @@ fn get_items_by_category(items: Vec<Record<Item>>, category: Option<String>) ->
         Some(c) => published
             .clone()
             .into_iter()
-            .filter(|p| {
-                p.clone()
-                    .fields
-                    .categories
-                    .unwrap()
-                    .into_iter()
-                    .rfind(|i| i == &c)
-                    .is_some()
-            })
+            .filter(|p| p.fields.categories.contains(&c))
             .collect(),
     }
 }
@@ -96,7 +385,9 @@ fn get_items_by_category(items: Vec<Record<Item>>, category: Option<String>) ->
 pub fn App() -> impl IntoView {
     let initial_items: Vec<Record<Item>> = vec![];
     let (items, set_items) = create_signal(initial_items);
+    let (categories, set_categories) = create_signal(Vec::<Record<Category>>::new());
     let data = create_resource(|| (), |_| async move { load_data().await });
+    let category_data = create_resource(|| (), |_| async move { load_categories().await });
 
     view! {
         <Stylesheet id="leptos" href="/pkg/unwedding-unregistry.css"/>
@@ -119,7 +410,7 @@ pub fn App() -> impl IntoView {
             <main class="flex px-8 pt-10 pb-24 flex-col items-center self-stretch text-gray-800">
                 <Routes>
                     <Route path="" view=  move || view! { <Home/> }/>
-                    <Route path="stuff" view=  move || view! { <Stuff stuff=items()/> }/>
+                    <Route path="stuff" view=  move || view! { <Stuff stuff=items() categories=categories()/> }/>
                     <Route path="cart" view=  move || view! { <Cart/> }/>
                 </Routes>
                 <Suspense fallback=|| ()>
@@ -138,6 +429,9 @@ pub fn App() -> impl IntoView {
                             }
                         }
                     }}
+                    {move || if let Some(Ok(c)) = category_data.get() {
+                        set_categories(c);
+                    }}
                 </Suspense>
             </main>
         </Router>
@@ -150,11 +444,14 @@ fn Home() -> impl IntoView {
 }
 
 #[component]
-fn Stuff(stuff: Vec<Record<Item>>) -> impl IntoView {
+fn Stuff(stuff: Vec<Record<Item>>, categories: Vec<Record<Category>>) -> impl IntoView {
     let initial_category: Option<String> = None;
     let (category, set_category) = create_signal(initial_category);
-    let categories = get_categories(stuff.clone());
-    let items = get_items_by_category(stuff.clone(), Some("sports".to_string())); //TODO
+    let sports = categories
+        .iter()
+        .find(|c| c.fields.name == "sports")
+        .map(|c| c.id.to_string());
+    let items = get_items_by_category(stuff.clone(), sports); //TODO
     info!("ITEMS: {:?}", items);
     view! {
         <div class="flex items-start">
@@ -162,13 +459,14 @@ fn Stuff(stuff: Vec<Record<Item>>) -> impl IntoView {
                 <ul>
                     {categories.into_iter()
                         .map(|c| {
-                            let text = c.clone();
+                            let text = c.fields.name;
+                            let id = c.id;
                             view! {
                                 <li class="mb-3 w-full">
                                     <button
                                         class="text-white bg-gray-400 font-bold rounded px-3 py-1"
                                         on:click=move |_| {
-                                            set_category(Some(c.to_string()));
+                                            set_category(Some(id.to_string()));
                                             info!("{:?}", category().unwrap());
                                         }
                                     >{text}</button>
@@ -182,7 +480,7 @@ fn Stuff(stuff: Vec<Record<Item>>) -> impl IntoView {
                 <div class="m-auto grid grid-cols-6 gap-4">
                     // TODO: fix reactivity
                     {items.into_iter()
-                        .filter(|i| i.fields.publish.is_some())
+                        .filter(|i| i.fields.publish.is_checked())
                         .map(|i| {
                             let item: Item = i.fields;
                             view! {
@@ -204,15 +502,20 @@ fn Cart() -> impl IntoView {
 pub fn ItemForSale(item: Item) -> impl IntoView {
     view! {
         <div class="bg-gray-100 p-3 rounded">
-            { if item.images.is_some() {
-                let images = item.images.unwrap();
-                images.first()
-                    .cloned()
-                    .into_iter()
-                    .map(|i| view! {<img src={i.thumbnails.large.url} class="rounded" />})
-                    .collect_view()
-                } else {
-                    view! {}.into_view()
+            { match item.images.unwrap_or_default().into_iter().next() {
+                    None => view! {
+                        <img src="/placeholder.svg" class="w-full p-8 opacity-25" />
+                    }.into_view(),
+                    Some(i) => match i.preview_url() {
+                        Some(url) => view! { <img src={url.to_string()} class="rounded" /> }.into_view(),
+                        // Documents without a preview, e.g. PDFs, link to the file.
+                        None => view! {
+                            <a href={i.url.clone()} class="flex flex-col items-center p-8">
+                                <img src="/document.svg" class="w-1/2 opacity-50" />
+                                <span class="text-sm mt-2">{i.filename.clone()}</span>
+                            </a>
+                        }.into_view(),
+                    },
                 }
             }
             <p class="text-2xl capitalize font-bold text-center m-2 text-gray-800">{item.name}</p>
@@ -220,7 +523,7 @@ pub fn ItemForSale(item: Item) -> impl IntoView {
                 <p class="text-sm items-center flex-1">{item.description}</p>
                 { if item.price.is_some() {
                         let price = item.price.unwrap();
-                        view! { <p class="font-bold text-2xl">{format!("${:.2}", price)}</p> }
+                        view! { <p class="font-bold text-2xl">{format!("${price}")}</p> }
                     } else {
                         view! { <p>tbd</p> }
                     }
@@ -230,3 +533,73 @@ pub fn ItemForSale(item: Item) -> impl IntoView {
         </div>
     }
 }
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+    use crate::airtable::fake::FakeAirtable;
+
+    #[tokio::test]
+    async fn loads_the_end_to_end_fixture() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/end2end/fixtures/airtable.json"
+        );
+        let fake = FakeAirtable::load("appFakeUnregistry", path).unwrap();
+        let addr = fake.spawn("127.0.0.1:0".parse().unwrap()).unwrap();
+        let airtable = fake.client(addr).unwrap();
+
+        // What load_data lists.
+        let items = items_table(&airtable)
+            .list_with(&items_options())
+            .await
+            .unwrap();
+        let names: Vec<_> = items
+            .iter()
+            .map(|i| i.fields.name.as_deref().unwrap_or_default())
+            .collect();
+        assert_eq!(names, ["stand mixer", "tennis racket", "yoga mat"]);
+        assert!(items.iter().all(|i| i.warnings.is_empty()));
+
+        // What load_categories lists.
+        let categories = airtable
+            .table::<Category>(CATEGORIES_TABLE)
+            .resolve(&items, |i| &i.categories)
+            .await
+            .unwrap();
+        let categories = get_categories(&items, &categories);
+        let names: Vec<_> = categories.iter().map(|c| c.fields.name.as_str()).collect();
+        assert_eq!(names, ["sports", "kitchen"]);
+
+        let reports = check_airtable_schema(&airtable).await.unwrap();
+        assert!(reports.iter().all(|r| r.is_ok()), "{reports:?}");
+    }
+
+    #[test]
+    fn reports_missing_items_only_for_item_requests() {
+        let not_found = |type_: &str| {
+            AirtableError::NotFound(ApiError {
+                type_: type_.to_string(),
+                message: String::new(),
+            })
+        };
+        let message = |e: ServerFnError| match e {
+            ServerFnError::ServerError(message) => message,
+            e => panic!("unexpected error {e:?}"),
+        };
+
+        assert_eq!(
+            message(item_error(not_found("NOT_FOUND"))),
+            "This item no longer exists."
+        );
+        // A list that isn't found means the table or base is missing.
+        assert_eq!(
+            message(server_error(not_found("NOT_FOUND"))),
+            "The registry is not set up correctly."
+        );
+        assert_eq!(
+            message(item_error(not_found("TABLE_NOT_FOUND"))),
+            "The registry is not set up correctly."
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Create the tables the registry expects in the Airtable base in
+//! `AIRTABLE_BASE_ID`.
+//!
+//! Usage: `airtable-bootstrap [--webhook URL]`
+//!
+//! Tables and fields that already exist are left alone, so this is safe to run
+//! against a base that is already set up.
+//!
+//! With `--webhook` a webhook notifying `URL` of record changes is created too,
+//! and the values for AIRTABLE_WEBHOOK_ID and AIRTABLE_WEBHOOK_SECRET printed.
+use std::{env, process};
+
+use unwedding_unregistry::{
+    airtable::{webhooks::NewWebhook, Airtable},
+    app::registry_tables,
+};
+
+#[tokio::main]
+async fn main() {
+    let mut webhook = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--webhook" => match args.next() {
+                Some(url) => webhook = Some(url),
+                None => fail("--webhook needs a notification URL"),
+            },
+            "-h" | "--help" => {
+                println!("usage: airtable-bootstrap [--webhook URL]");
+                return;
+            }
+            _ => fail(&format!("unexpected argument: {arg}")),
+        }
+    }
+
+    let base_id = env::var("AIRTABLE_BASE_ID").unwrap_or_default();
+    if base_id.is_empty() {
+        fail("AIRTABLE_BASE_ID must be set");
+    }
+
+    let airtable = match Airtable::new_from_env() {
+        Ok(airtable) => airtable,
+        Err(e) => fail(&format!("creating the airtable client failed: {e}")),
+    };
+    let tables = match airtable.bootstrap(&registry_tables()).await {
+        Ok(tables) => tables,
+        Err(e) => fail(&format!("bootstrapping base {base_id} failed: {e}")),
+    };
+
+    for table in tables {
+        println!("{} ({})", table.name, table.id);
+    }
+
+    if let Some(url) = webhook {
+        let created = match airtable
+            .create_webhook(&NewWebhook::records(url, None))
+            .await
+        {
+            Ok(created) => created,
+            Err(e) => fail(&format!("creating the webhook failed: {e}")),
+        };
+
+        println!("AIRTABLE_WEBHOOK_ID={}", created.id);
+        println!("AIRTABLE_WEBHOOK_SECRET={}", created.mac_secret_base64);
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("airtable-bootstrap: {message}");
+    process::exit(1);
+}
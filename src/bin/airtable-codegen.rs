@@ -0,0 +1,73 @@
+//! Generate Rust record types from the schema of the Airtable base in
+//! `AIRTABLE_BASE_ID`.
+//!
+//! Usage: `airtable-codegen [--table NAME]... [OUTPUT]`
+//!
+//! Without `--table` every table in the base is generated. The module is
+//! written to `OUTPUT`, or to stdout when no path is given.
+use std::{env, fs, process};
+
+use unwedding_unregistry::airtable::{codegen, Airtable};
+
+#[tokio::main]
+async fn main() {
+    let mut tables = Vec::new();
+    let mut output = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--table" => match args.next() {
+                Some(table) => tables.push(table),
+                None => fail("--table needs a table name"),
+            },
+            "-h" | "--help" => {
+                println!("usage: airtable-codegen [--table NAME]... [OUTPUT]");
+                return;
+            }
+            _ if output.is_none() => output = Some(arg),
+            _ => fail(&format!("unexpected argument: {arg}")),
+        }
+    }
+
+    let base_id = env::var("AIRTABLE_BASE_ID").unwrap_or_default();
+    if base_id.is_empty() {
+        fail("AIRTABLE_BASE_ID must be set");
+    }
+
+    let airtable = match Airtable::new_from_env() {
+        Ok(airtable) => airtable,
+        Err(e) => fail(&format!("creating the airtable client failed: {e}")),
+    };
+    let mut schema = match airtable.list_tables().await {
+        Ok(schema) => schema,
+        Err(e) => fail(&format!(
+            "fetching the schema of base {base_id} failed: {e}"
+        )),
+    };
+
+    if !tables.is_empty() {
+        for table in &tables {
+            if !schema.iter().any(|t| &t.name == table || &t.id == table) {
+                fail(&format!("table {table} does not exist in base {base_id}"));
+            }
+        }
+        schema.retain(|t| tables.contains(&t.name) || tables.contains(&t.id));
+    }
+
+    let code = codegen::generate(&base_id, &schema);
+
+    match output {
+        Some(path) => {
+            if let Err(e) = fs::write(&path, code) {
+                fail(&format!("writing {path} failed: {e}"));
+            }
+        }
+        None => print!("{code}"),
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("airtable-codegen: {message}");
+    process::exit(1);
+}
@@ -0,0 +1,66 @@
+//! Serve an in-memory Airtable with the records of a fixture, to run the site
+//! and the end to end tests without a real base.
+//!
+//! Usage: `fake-airtable [--port PORT] [--base BASE_ID] [FIXTURE]`
+//!
+//! The base id defaults to `AIRTABLE_BASE_ID`, or `appFakeUnregistry`. Point
+//! the site at the printed AIRTABLE_ENDPOINT, any API key will do. Changes are
+//! kept in memory and lost when it stops.
+use std::{env, net::SocketAddr, process};
+
+use unwedding_unregistry::airtable::fake::FakeAirtable;
+
+const DEFAULT_BASE_ID: &str = "appFakeUnregistry";
+
+#[tokio::main]
+async fn main() {
+    let mut port: u16 = 3001;
+    let mut base_id = env::var("AIRTABLE_BASE_ID")
+        .ok()
+        .filter(|b| !b.is_empty())
+        .unwrap_or_else(|| DEFAULT_BASE_ID.to_string());
+    let mut fixture = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => match args.next().map(|p| p.parse()) {
+                Some(Ok(p)) => port = p,
+                _ => fail("--port needs a port number"),
+            },
+            "--base" => match args.next() {
+                Some(b) => base_id = b,
+                None => fail("--base needs a base id"),
+            },
+            "-h" | "--help" => {
+                println!("usage: fake-airtable [--port PORT] [--base BASE_ID] [FIXTURE]");
+                return;
+            }
+            _ if fixture.is_none() && !arg.starts_with('-') => fixture = Some(arg),
+            _ => fail(&format!("unexpected argument: {arg}")),
+        }
+    }
+
+    let fake = match &fixture {
+        Some(path) => match FakeAirtable::load(&base_id, path) {
+            Ok(fake) => fake,
+            Err(e) => fail(&format!("loading {path} failed: {e}")),
+        },
+        None => FakeAirtable::new(&base_id),
+    };
+
+    let addr = match fake.spawn(SocketAddr::from(([127, 0, 0, 1], port))) {
+        Ok(addr) => addr,
+        Err(e) => fail(&format!("listening on port {port} failed: {e}")),
+    };
+
+    println!("AIRTABLE_ENDPOINT={}", FakeAirtable::endpoint(addr));
+    println!("AIRTABLE_BASE_ID={base_id}");
+
+    std::future::pending::<()>().await;
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("fake-airtable: {message}");
+    process::exit(1);
+}
@@ -2,6 +2,8 @@ use cfg_if::cfg_if;
 pub mod app;
 pub mod fallback;
 pub mod airtable;
+pub mod media;
+pub mod webhooks;
 
 cfg_if! { if #[cfg(feature = "hydrate")] {
     use leptos::*;
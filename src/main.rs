@@ -1,14 +1,60 @@
 #[cfg(feature = "ssr")]
 #[tokio::main]
 async fn main() {
-    use axum::{routing::get, Router};
+    use axum::{
+        body::Body,
+        extract::{Path, RawQuery},
+        http::{HeaderMap, Request},
+        routing::{get, post},
+        Router,
+    };
     use leptos::*;
-    use leptos_axum::{generate_route_list, LeptosRoutes};
+    use leptos_axum::{generate_route_list, handle_server_fns_with_context, LeptosRoutes};
     use log::info;
-    use unwedding_unregistry::{app::*, fallback::file_and_error_handler};
+    use unwedding_unregistry::{
+        airtable::Airtable,
+        app::*,
+        fallback::file_and_error_handler,
+        media::{serve_media, AttachmentCache},
+        webhooks::{receive_webhook, WebhookReceiver},
+    };
 
     simple_logger::init_with_level(log::Level::Info).expect("couldn't initialize logging");
 
+    let airtable = match Airtable::new_from_env() {
+        Ok(airtable) => airtable,
+        Err(e) => {
+            log::error!("airtable client setup failed: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    // Make sure the Airtable base has what the site needs before serving it.
+    // Set AIRTABLE_STRICT_SCHEMA to refuse to start when it doesn't.
+    let problems: Vec<String> = match check_airtable_schema(&airtable).await {
+        Ok(reports) => reports
+            .into_iter()
+            .filter(|r| !r.is_ok())
+            .map(|r| r.to_string())
+            .collect(),
+        Err(e) => vec![e.to_string()],
+    };
+    for problem in &problems {
+        log::error!("airtable schema check failed: {problem}");
+    }
+    if !problems.is_empty() && std::env::var("AIRTABLE_STRICT_SCHEMA").is_ok() {
+        std::process::exit(1);
+    }
+
+    // Listen for changes in the base when a webhook is configured.
+    let webhooks = match WebhookReceiver::from_env(airtable).await {
+        Ok(webhooks) => webhooks,
+        Err(e) => {
+            log::error!("airtable webhook setup failed: {e}");
+            None
+        }
+    };
+
     // Setting get_configuration(None) means we'll be using cargo-leptos's env values
     // For deployment these variables are:
     // <https://github.com/leptos-rs/start-axum#executing-a-server-on-a-remote-machine-without-the-toolchain>
@@ -20,10 +66,61 @@ async fn main() {
     // Generate the list of routes in your Leptos App
     let routes = generate_route_list(App);
 
+    // Keep the item list while the webhook reports no changes to the base.
+    let items = webhooks.as_ref().map(|receiver| {
+        let items = ItemCache::default();
+        items.clear_on_changes(receiver.subscribe());
+        items
+    });
+
+    // Attachments are served from a local copy, the Airtable urls expire.
+    let media = match AttachmentCache::from_env() {
+        Ok(media) => media,
+        Err(e) => {
+            log::error!("media cache setup failed: {e}");
+            std::process::exit(1);
+        }
+    };
+    let app_context = {
+        let media = media.clone();
+        move || {
+            provide_context(media.clone());
+            if let Some(items) = &items {
+                provide_context(items.clone());
+            }
+        }
+    };
+
     // build our application with a route
-    let app = Router::new()
-        .route("/api/*fn_name", get(leptos_axum::handle_server_fns))
-        .leptos_routes(&leptos_options, routes, || view! { <App/> })
+    let server_fn_context = app_context.clone();
+    let mut app = Router::new()
+        .route(
+            "/api/*fn_name",
+            get(
+                move |path: Path<String>,
+                      headers: HeaderMap,
+                      query: RawQuery,
+                      req: Request<Body>| {
+                    handle_server_fns_with_context(
+                        path,
+                        headers,
+                        query,
+                        server_fn_context.clone(),
+                        req,
+                    )
+                },
+            ),
+        )
+        .route("/media/:id/:size", get(serve_media).with_state(media));
+    if let Some(receiver) = webhooks {
+        receiver.spawn_refresh();
+        app = app.route(
+            "/webhooks/airtable",
+            post(receive_webhook).with_state(receiver),
+        );
+    }
+    let app = app
+        .leptos_routes_with_context(&leptos_options, routes, app_context, || view! { <App/> })
         .fallback(file_and_error_handler)
         .with_state(leptos_options);
 
@@ -0,0 +1,428 @@
+//! Serve Airtable attachments from a local cache. Airtable attachment and
+//! thumbnail urls expire after a few hours, so pages rendered with them end
+//! up with broken images once cached.
+//!
+//! Listed attachments are registered with the [`AttachmentCache`], which
+//! points their urls at the `/media/{id}/{size}` route. The first request for
+//! a file downloads it from the latest registered Airtable url and keeps it on
+//! disk, later requests are served from there.
+//!
+//! The urls are only kept in memory. After a restart a file that wasn't
+//! downloaded yet is not found until the attachment is registered again, i.e.
+//! until a page listing it is rendered. Those responses are not cached, so the
+//! file shows up on the next load.
+//!
+//! Nothing is ever removed from the cache directory, it grows with every
+//! attachment listed, including attachments since replaced or deleted in
+//! Airtable. Clearing the directory is safe, the files are downloaded again
+//! from the next registered urls.
+use cfg_if::cfg_if;
+
+cfg_if! { if #[cfg(feature = "ssr")] {
+    use std::{
+        collections::HashMap,
+        env, fmt, io,
+        path::PathBuf,
+        str::FromStr,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use axum::{
+        body::StreamBody,
+        extract::{Path, State},
+        http::{header, StatusCode},
+        response::{IntoResponse, Response},
+    };
+    use tokio::{fs::File, io::AsyncWriteExt};
+    use tokio_util::io::ReaderStream;
+
+    use crate::airtable::Attachment;
+
+    /// Where the attachments are kept unless MEDIA_CACHE_DIR is set.
+    const DEFAULT_DIR: &str = "target/media";
+    /// Sent with the cached files, a file never changes for an id and size.
+    const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+    /// How long downloading a file from Airtable may take.
+    const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// A version of an attachment: the uploaded file or one of its thumbnails.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum MediaSize {
+        Original,
+        Small,
+        Large,
+        Full,
+    }
+
+    impl fmt::Display for MediaSize {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(match self {
+                MediaSize::Original => "original",
+                MediaSize::Small => "small",
+                MediaSize::Large => "large",
+                MediaSize::Full => "full",
+            })
+        }
+    }
+
+    impl FromStr for MediaSize {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "original" => Ok(MediaSize::Original),
+                "small" => Ok(MediaSize::Small),
+                "large" => Ok(MediaSize::Large),
+                "full" => Ok(MediaSize::Full),
+                _ => Err(()),
+            }
+        }
+    }
+
+    /// A file in the cache, an attachment id and size.
+    type Key = (String, MediaSize);
+
+    /// Downloads attachments once and keeps them on disk, keyed by attachment
+    /// id and size.
+    #[derive(Clone)]
+    pub struct AttachmentCache {
+        inner: Arc<Inner>,
+    }
+
+    struct Inner {
+        dir: PathBuf,
+        client: reqwest::Client,
+        /// The latest Airtable url of every registered file not downloaded yet.
+        urls: Mutex<HashMap<Key, String>>,
+        /// Held while a file is downloaded, so it is only downloaded once. An
+        /// entry is removed once no request waits on it.
+        downloads: Mutex<HashMap<Key, Arc<tokio::sync::Mutex<()>>>>,
+    }
+
+    /// A request's hold on the download lock of a file, dropping the lock from
+    /// the map when it is the last one, even if the request is cancelled.
+    struct DownloadLock<'a> {
+        inner: &'a Inner,
+        key: Key,
+        lock: Arc<tokio::sync::Mutex<()>>,
+    }
+
+    impl<'a> DownloadLock<'a> {
+        fn new(inner: &'a Inner, key: Key) -> Self {
+            let lock = inner
+                .downloads
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(key.clone())
+                .or_default()
+                .clone();
+            DownloadLock { inner, key, lock }
+        }
+    }
+
+    impl Drop for DownloadLock<'_> {
+        fn drop(&mut self) {
+            let mut downloads = self
+                .inner
+                .downloads
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            // Held by the map and this request only.
+            if Arc::strong_count(&self.lock) == 2 {
+                downloads.remove(&self.key);
+            }
+        }
+    }
+
+    impl AttachmentCache {
+        /// Keep the attachments in `dir`, which is created when needed. Fails
+        /// when the download client can't be created, e.g. without TLS support.
+        pub fn new(dir: impl Into<PathBuf>) -> Result<Self, reqwest::Error> {
+            let client = reqwest::Client::builder()
+                .timeout(DOWNLOAD_TIMEOUT)
+                .build()?;
+
+            Ok(AttachmentCache {
+                inner: Arc::new(Inner {
+                    dir: dir.into(),
+                    client,
+                    urls: Default::default(),
+                    downloads: Default::default(),
+                }),
+            })
+        }
+
+        /// Keep the attachments in the directory in the MEDIA_CACHE_DIR env
+        /// variable, or in `target/media`.
+        pub fn from_env() -> Result<Self, reqwest::Error> {
+            Self::new(env::var("MEDIA_CACHE_DIR").unwrap_or_else(|_| DEFAULT_DIR.to_string()))
+        }
+
+        /// The route serving an attachment.
+        pub fn path(id: &str, size: MediaSize) -> String {
+            format!("/media/{id}/{size}")
+        }
+
+        /// Remember the Airtable urls of an attachment and replace them with
+        /// the routes serving it from the cache. Thumbnails Airtable didn't
+        /// send and relative urls, e.g. from a fixture, are left alone. The
+        /// urls of files already on disk aren't kept.
+        pub fn register(&self, attachment: &mut Attachment) {
+            if !is_valid_id(&attachment.id) {
+                return;
+            }
+
+            let mut files = vec![(MediaSize::Original, &mut attachment.url)];
+            if let Some(thumbnails) = &mut attachment.thumbnails {
+                for (size, thumbnail) in [
+                    (MediaSize::Small, &mut thumbnails.small),
+                    (MediaSize::Large, &mut thumbnails.large),
+                    (MediaSize::Full, &mut thumbnails.full),
+                ] {
+                    if let Some(thumbnail) = thumbnail {
+                        files.push((size, &mut thumbnail.url));
+                    }
+                }
+            }
+
+            let mut urls = self.inner.urls.lock().unwrap_or_else(|e| e.into_inner());
+            for (size, url) in files {
+                if reqwest::Url::parse(url).is_err() {
+                    continue;
+                }
+                let url = std::mem::replace(url, Self::path(&attachment.id, size));
+                if !self.file(&attachment.id, size).exists() {
+                    urls.insert((attachment.id.to_string(), size), url);
+                }
+            }
+        }
+
+        fn file(&self, id: &str, size: MediaSize) -> PathBuf {
+            self.inner.dir.join(id).join(size.to_string())
+        }
+
+        /// The file and its content type, downloading it first if it isn't
+        /// cached yet.
+        async fn get(
+            &self,
+            id: &str,
+            size: MediaSize,
+        ) -> Result<(File, String), (StatusCode, String)> {
+            let key: Key = (id.to_string(), size);
+            let download = DownloadLock::new(&self.inner, key.clone());
+            let _guard = download.lock.lock().await;
+
+            let file = self.file(id, size);
+            let content_type = file.with_extension("type");
+            match File::open(&file).await {
+                Ok(body) => {
+                    let content_type = tokio::fs::read_to_string(&content_type)
+                        .await
+                        .unwrap_or_default();
+                    return Ok((body, content_type));
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => return Err(internal_error(e)),
+            }
+
+            let url = self
+                .inner
+                .urls
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(&key)
+                .cloned();
+            let Some(url) = url else {
+                return Err((StatusCode::NOT_FOUND, format!("unknown attachment {id}")));
+            };
+
+            log::info!("downloading attachment {id} ({size})");
+            let download_error = |e: reqwest::Error| {
+                (StatusCode::BAD_GATEWAY, format!("downloading {id} failed: {e}"))
+            };
+            let mut resp = self
+                .inner
+                .client
+                .get(&url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(download_error)?;
+            let type_ = resp
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+
+            // Write to a temporary file first so a failed download never leaves
+            // a partial file behind to be served.
+            tokio::fs::create_dir_all(self.inner.dir.join(id))
+                .await
+                .map_err(internal_error)?;
+            let partial = file.with_extension("partial");
+            let mut out = File::create(&partial).await.map_err(internal_error)?;
+            while let Some(chunk) = resp.chunk().await.map_err(download_error)? {
+                out.write_all(&chunk).await.map_err(internal_error)?;
+            }
+            let write = async {
+                out.flush().await?;
+                tokio::fs::write(&content_type, &type_).await?;
+                tokio::fs::rename(&partial, &file).await
+            };
+            write.await.map_err(internal_error)?;
+
+            // The file is on disk, its url is no longer needed.
+            self.inner
+                .urls
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&key);
+
+            let body = File::open(&file).await.map_err(internal_error)?;
+            Ok((body, type_))
+        }
+    }
+
+    /// Attachment ids are letters and digits, e.g. `attXXXXXXXXXXXXXX`, so an
+    /// id never leaves the cache directory.
+    fn is_valid_id(id: &str) -> bool {
+        !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric())
+    }
+
+    fn internal_error(e: io::Error) -> (StatusCode, String) {
+        log::error!("attachment cache failed: {e}");
+        (StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong.".to_string())
+    }
+
+    /// The `/media/:id/:size` route.
+    pub async fn serve_media(
+        State(cache): State<AttachmentCache>,
+        Path((id, size)): Path<(String, String)>,
+    ) -> Response {
+        let Ok(size) = size.parse::<MediaSize>() else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        if !is_valid_id(&id) {
+            return StatusCode::NOT_FOUND.into_response();
+        }
+
+        match cache.get(&id, size).await {
+            Ok((body, content_type)) => {
+                let content_type = match content_type.is_empty() {
+                    true => "application/octet-stream".to_string(),
+                    false => content_type,
+                };
+                (
+                    [
+                        (header::CONTENT_TYPE, content_type),
+                        (header::CACHE_CONTROL, CACHE_CONTROL.to_string()),
+                    ],
+                    StreamBody::new(ReaderStream::new(body)),
+                )
+                    .into_response()
+            }
+            Err(e) => {
+                log::warn!("serving attachment {id} ({size}) failed: {}", e.1);
+                // Not cached, the file may be found once it is registered again.
+                ([(header::CACHE_CONTROL, "no-store")], e).into_response()
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::net::TcpListener;
+
+        use axum::{body::HttpBody, routing::get, Router};
+
+        use super::*;
+
+        fn cache(name: &str) -> AttachmentCache {
+            let dir = env::temp_dir().join(format!("media-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            AttachmentCache::new(dir).unwrap()
+        }
+
+        async fn serve(cache: &AttachmentCache, id: &str, size: &str) -> Response {
+            serve_media(
+                State(cache.clone()),
+                Path((id.to_string(), size.to_string())),
+            )
+            .await
+        }
+
+        async fn body(resp: Response) -> Vec<u8> {
+            let mut body = resp.into_body();
+            let mut bytes = Vec::new();
+            while let Some(chunk) = body.data().await {
+                bytes.extend_from_slice(&chunk.unwrap());
+            }
+            bytes
+        }
+
+        #[tokio::test]
+        async fn rejects_ids_leaving_the_cache() {
+            let cache = cache("ids");
+            for id in ["..", "../attOther", "att/../..", "att%2F..", ""] {
+                assert!(!is_valid_id(id), "{id:?}");
+                assert_eq!(
+                    serve(&cache, id, "original").await.status(),
+                    StatusCode::NOT_FOUND
+                );
+            }
+            assert_eq!(
+                serve(&cache, "attValid", "huge").await.status(),
+                StatusCode::NOT_FOUND
+            );
+
+            let mut attachment = Attachment {
+                id: "../attOther".to_string(),
+                url: "https://example.com/file.png".to_string(),
+                ..Default::default()
+            };
+            cache.register(&mut attachment);
+            assert_eq!(attachment.url, "https://example.com/file.png");
+            assert!(cache.inner.urls.lock().unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn downloads_a_file_once() {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let app = Router::new().route(
+                "/file.png",
+                get(|| async { ([(header::CONTENT_TYPE, "image/png")], "png bytes") }),
+            );
+            let server = axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service());
+            tokio::spawn(server);
+
+            let cache = cache("download");
+            let mut attachment = Attachment {
+                id: "attDownload".to_string(),
+                url: format!("http://{addr}/file.png"),
+                ..Default::default()
+            };
+            cache.register(&mut attachment);
+            assert_eq!(attachment.url, "/media/attDownload/original");
+
+            let resp = serve(&cache, "attDownload", "original").await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.headers()[header::CONTENT_TYPE], "image/png");
+            assert_eq!(body(resp).await, b"png bytes");
+
+            // Only the file on disk is left.
+            assert!(cache.inner.urls.lock().unwrap().is_empty());
+            assert!(cache.inner.downloads.lock().unwrap().is_empty());
+            let resp = serve(&cache, "attDownload", "original").await;
+            assert_eq!(body(resp).await, b"png bytes");
+
+            let resp = serve(&cache, "attUnknown", "original").await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            assert_eq!(resp.headers()[header::CACHE_CONTROL], "no-store");
+        }
+    }
+}}
@@ -0,0 +1,377 @@
+//! Receive Airtable webhook notifications and pass the changed record ids on to
+//! whoever subscribed, e.g. to invalidate a cached item list.
+use cfg_if::cfg_if;
+
+cfg_if! { if #[cfg(feature = "ssr")] {
+    use std::{
+        env,
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::Duration,
+    };
+
+    use axum::{
+        body::Bytes,
+        extract::State,
+        http::{HeaderMap, StatusCode},
+    };
+    use tokio::sync::{broadcast, Mutex};
+
+    use crate::airtable::{
+        webhooks::{verify_mac, WebhookNotification, MAC_HEADER},
+        Airtable, AirtableError, ApiError,
+    };
+
+    /// How often the webhook is refreshed, it expires after 7 days.
+    const REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// The records of a table that were created, changed or destroyed.
+    #[derive(Debug, Clone)]
+    pub struct RecordChanges {
+        pub table_id: String,
+        pub created: Vec<String>,
+        pub changed: Vec<String>,
+        pub destroyed: Vec<String>,
+    }
+
+    /// Fetches the payloads of a webhook when Airtable pings us and hands the
+    /// changes to the subscribers.
+    #[derive(Clone)]
+    pub struct WebhookReceiver {
+        inner: Arc<Inner>,
+    }
+
+    struct Inner {
+        airtable: Airtable,
+        webhook_id: String,
+        mac_secret: String,
+        cursor: Mutex<u64>,
+        /// Where the cursor is kept, so a restart picks up where we left off.
+        cursor_file: Option<PathBuf>,
+        changes: broadcast::Sender<RecordChanges>,
+    }
+
+    impl WebhookReceiver {
+        /// Receive the notifications of `webhook_id`, starting at the payload
+        /// at `cursor`. The cursor is written to `cursor_file` as the payloads
+        /// are fetched, without one it is only kept in memory.
+        pub fn new(
+            airtable: Airtable,
+            webhook_id: &str,
+            mac_secret_base64: &str,
+            cursor: u64,
+            cursor_file: Option<PathBuf>,
+        ) -> Self {
+            let (changes, _) = broadcast::channel(64);
+
+            WebhookReceiver {
+                inner: Arc::new(Inner {
+                    airtable,
+                    webhook_id: webhook_id.to_string(),
+                    mac_secret: mac_secret_base64.to_string(),
+                    cursor: Mutex::new(cursor),
+                    cursor_file,
+                    changes,
+                }),
+            }
+        }
+
+        /// Receive the notifications of the webhook in the AIRTABLE_WEBHOOK_ID
+        /// env variable, signed with the secret in AIRTABLE_WEBHOOK_SECRET.
+        ///
+        /// The cursor is kept in the file in AIRTABLE_WEBHOOK_CURSOR_FILE, and
+        /// the changes made since it was written are passed on after a restart.
+        /// Without that file only changes made from now on are passed on.
+        ///
+        /// Returns `None` when the webhook is not configured.
+        pub async fn from_env(airtable: Airtable) -> Result<Option<Self>, AirtableError> {
+            let webhook_id = env::var("AIRTABLE_WEBHOOK_ID").unwrap_or_default();
+            let mac_secret = env::var("AIRTABLE_WEBHOOK_SECRET").unwrap_or_default();
+            if webhook_id.is_empty() || mac_secret.is_empty() {
+                return Ok(None);
+            }
+
+            let webhook = airtable
+                .list_webhooks()
+                .await?
+                .into_iter()
+                .find(|w| w.id == webhook_id)
+                .ok_or_else(|| {
+                    AirtableError::NotFound(ApiError {
+                        type_: "WEBHOOK_NOT_FOUND".to_string(),
+                        message: format!("the base has no webhook with id {webhook_id}"),
+                    })
+                })?;
+
+            let cursor_file = env::var("AIRTABLE_WEBHOOK_CURSOR_FILE")
+                .ok()
+                .filter(|f| !f.is_empty())
+                .map(PathBuf::from);
+            let saved = match &cursor_file {
+                Some(file) => saved_cursor(file).await,
+                None => None,
+            };
+
+            Ok(Some(Self::new(
+                airtable,
+                &webhook_id,
+                &mac_secret,
+                saved.unwrap_or(webhook.cursor_for_next_payload),
+                cursor_file,
+            )))
+        }
+
+        /// Subscribe to the changes.
+        pub fn subscribe(&self) -> broadcast::Receiver<RecordChanges> {
+            self.inner.changes.subscribe()
+        }
+
+        /// Fetch the payloads since the last call and hand the changes to the
+        /// subscribers.
+        pub async fn fetch_changes(&self) -> Result<(), AirtableError> {
+            // Hold the cursor so concurrent notifications don't fetch the same
+            // payloads twice.
+            let mut cursor = self.inner.cursor.lock().await;
+
+            loop {
+                let page = self
+                    .inner
+                    .airtable
+                    .list_webhook_payloads(&self.inner.webhook_id, *cursor)
+                    .await?;
+
+                for payload in page.payloads {
+                    for (table_id, changes) in payload.changed_tables_by_id {
+                        let changes = RecordChanges {
+                            table_id,
+                            created: changes.created_records_by_id.into_keys().collect(),
+                            changed: changes.changed_records_by_id.into_keys().collect(),
+                            destroyed: changes.destroyed_record_ids,
+                        };
+                        log::debug!("airtable records changed: {changes:?}");
+
+                        // Nobody listening is fine.
+                        let _ = self.inner.changes.send(changes);
+                    }
+                }
+
+                *cursor = page.cursor;
+                if let Some(file) = &self.inner.cursor_file {
+                    if let Err(e) = tokio::fs::write(file, cursor.to_string()).await {
+                        log::error!("saving the airtable webhook cursor failed: {e}");
+                    }
+                }
+                if !page.might_have_more {
+                    return Ok(());
+                }
+            }
+        }
+
+        /// Keep refreshing the webhook in the background so it doesn't expire.
+        pub fn spawn_refresh(&self) {
+            let receiver = self.clone();
+            tokio::spawn(async move {
+                loop {
+                    if let Err(e) = receiver
+                        .inner
+                        .airtable
+                        .refresh_webhook(&receiver.inner.webhook_id)
+                        .await
+                    {
+                        log::error!("refreshing the airtable webhook failed: {e}");
+                    }
+                    tokio::time::sleep(REFRESH_INTERVAL).await;
+                }
+            });
+        }
+    }
+
+    /// The cursor written to `file` by [`WebhookReceiver::fetch_changes`].
+    async fn saved_cursor(file: &Path) -> Option<u64> {
+        tokio::fs::read_to_string(file)
+            .await
+            .ok()
+            .and_then(|c| c.trim().parse().ok())
+    }
+
+    /// The route Airtable pings. Verifies the notification and fetches the
+    /// changes in the background, Airtable expects a quick answer.
+    pub async fn receive_webhook(
+        State(receiver): State<WebhookReceiver>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> StatusCode {
+        let mac = headers
+            .get(MAC_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        if !verify_mac(&receiver.inner.mac_secret, &body, mac) {
+            return StatusCode::UNAUTHORIZED;
+        }
+
+        let notification: WebhookNotification = match serde_json::from_slice(&body) {
+            Ok(notification) => notification,
+            Err(_) => return StatusCode::BAD_REQUEST,
+        };
+        if notification.webhook.id != receiver.inner.webhook_id {
+            return StatusCode::NOT_FOUND;
+        }
+
+        tokio::spawn(async move {
+            if let Err(e) = receiver.fetch_changes().await {
+                log::error!("fetching the airtable webhook payloads failed: {e}");
+            }
+        });
+
+        StatusCode::NO_CONTENT
+    }
+    #[cfg(test)]
+    mod tests {
+        use std::{collections::HashMap, net::TcpListener};
+
+        use axum::{extract::Query, http::HeaderValue, routing::get, Json, Router};
+        use serde_json::json;
+
+        use super::*;
+        use crate::airtable::AirtableBuilder;
+
+        const SECRET: &str = "dW5yZWdpc3RyeS13ZWJob29rLXNlY3JldA==";
+        const BODY: &[u8] = br#"{"base":{"id":"appFakeUnregistry"},"webhook":{"id":"achFakeWebhook"},"timestamp":"2023-10-01T12:00:00.000Z"}"#;
+        const MAC: &str =
+            "hmac-sha256=e8adc65a84bb086c70b72ae963d5d435fe1b464bb086a9dcc6999ef97c665105";
+
+        /// Serves the payloads of a webhook in pages of one, up to cursor 3.
+        async fn serve_payloads() -> String {
+            let app = Router::new().route(
+                "/v0/bases/:base/webhooks/:webhook/payloads",
+                get(|Query(query): Query<HashMap<String, String>>| async move {
+                    let cursor: u64 = query["cursor"].parse().unwrap();
+                    let payloads = match cursor {
+                        1 => json!([{
+                            "timestamp": "2023-10-01T12:00:00.000Z",
+                            "baseTransactionNumber": 1,
+                            "changedTablesById": {
+                                "tblItems": { "changedRecordsById": { "rec1": {} } }
+                            }
+                        }]),
+                        2 => json!([{
+                            "timestamp": "2023-10-01T12:01:00.000Z",
+                            "baseTransactionNumber": 2,
+                            "changedTablesById": {
+                                "tblItems": { "destroyedRecordIds": ["rec2"] }
+                            }
+                        }]),
+                        _ => json!([]),
+                    };
+                    Json(json!({
+                        "cursor": cursor.min(2) + 1,
+                        "mightHaveMore": cursor < 2,
+                        "payloads": payloads,
+                    }))
+                }),
+            );
+
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(
+                axum::Server::from_tcp(listener)
+                    .unwrap()
+                    .serve(app.into_make_service()),
+            );
+
+            format!("http://{addr}/v0/")
+        }
+
+        fn receiver(endpoint: &str, cursor: u64, cursor_file: Option<PathBuf>) -> WebhookReceiver {
+            let airtable = AirtableBuilder::new("key", "appFakeUnregistry")
+                .endpoint(endpoint)
+                .build()
+                .unwrap();
+            WebhookReceiver::new(airtable, "achFakeWebhook", SECRET, cursor, cursor_file)
+        }
+
+        async fn receive(receiver: &WebhookReceiver, mac: Option<&str>, body: &[u8]) -> StatusCode {
+            let mut headers = HeaderMap::new();
+            if let Some(mac) = mac {
+                headers.insert(MAC_HEADER, HeaderValue::from_str(mac).unwrap());
+            }
+            receive_webhook(
+                State(receiver.clone()),
+                headers,
+                Bytes::copy_from_slice(body),
+            )
+            .await
+        }
+
+        #[tokio::test]
+        async fn fetches_changes_and_saves_the_cursor() {
+            let endpoint = serve_payloads().await;
+            let file = env::temp_dir().join(format!("webhook-cursor-{}", std::process::id()));
+            let _ = std::fs::remove_file(&file);
+
+            let receiver = receiver(&endpoint, 1, Some(file.clone()));
+            let mut changes = receiver.subscribe();
+            receiver.fetch_changes().await.unwrap();
+
+            let first = changes.try_recv().unwrap();
+            assert_eq!(first.table_id, "tblItems");
+            assert_eq!(first.changed, ["rec1"]);
+            let second = changes.try_recv().unwrap();
+            assert_eq!(second.destroyed, ["rec2"]);
+            assert!(changes.try_recv().is_err());
+
+            // A restart picks up after the last page.
+            assert_eq!(saved_cursor(&file).await, Some(3));
+
+            // Without new payloads nothing is passed on and the cursor stays.
+            let receiver = self::receiver(&endpoint, 3, Some(file.clone()));
+            let mut changes = receiver.subscribe();
+            receiver.fetch_changes().await.unwrap();
+            assert!(changes.try_recv().is_err());
+            assert_eq!(saved_cursor(&file).await, Some(3));
+
+            std::fs::remove_file(&file).unwrap();
+        }
+
+        #[tokio::test]
+        async fn ignores_a_missing_cursor_file() {
+            let file = env::temp_dir().join(format!("webhook-missing-{}", std::process::id()));
+            assert_eq!(saved_cursor(&file).await, None);
+        }
+
+        #[tokio::test]
+        async fn rejects_notifications_with_a_bad_mac() {
+            // Nothing is fetched from the endpoint when the MAC doesn't match.
+            let receiver = receiver("http://127.0.0.1:9/v0/", 1, None);
+
+            let tampered = MAC.replace("e8ad", "e8ae");
+            assert_eq!(
+                receive(&receiver, Some(&tampered), BODY).await,
+                StatusCode::UNAUTHORIZED
+            );
+            assert_eq!(
+                receive(&receiver, None, BODY).await,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+
+        #[tokio::test]
+        async fn accepts_notifications_for_the_webhook() {
+            let endpoint = serve_payloads().await;
+            let receiver = receiver(&endpoint, 1, None);
+            let mut changes = receiver.subscribe();
+
+            assert_eq!(
+                receive(&receiver, Some(MAC), BODY).await,
+                StatusCode::NO_CONTENT
+            );
+
+            // The payloads are fetched in the background.
+            let changes = tokio::time::timeout(Duration::from_secs(5), changes.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(changes.changed, ["rec1"]);
+        }
+    }
+}}